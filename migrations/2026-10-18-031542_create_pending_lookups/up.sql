@@ -0,0 +1,7 @@
+CREATE TABLE pending_lookups (
+  endpoint INTEGER NOT NULL REFERENCES endpoints(id) ON DELETE RESTRICT ON UPDATE CASCADE,
+  authenticated_user INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  user INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  target INTEGER NOT NULL,
+  PRIMARY KEY (endpoint, authenticated_user, user, target)
+);
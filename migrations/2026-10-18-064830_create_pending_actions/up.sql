@@ -0,0 +1,6 @@
+CREATE TABLE pending_actions (
+  authenticated_user INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  target INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  action TEXT NOT NULL,
+  PRIMARY KEY (authenticated_user, target, action)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE failed_actions (
+  authenticated_user INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  target INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  action TEXT NOT NULL,
+  attempts INTEGER NOT NULL,
+  status INTEGER,
+  code INTEGER,
+  error TEXT NOT NULL,
+  failed_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+  PRIMARY KEY (authenticated_user, target, action)
+);
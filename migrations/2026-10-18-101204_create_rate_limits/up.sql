@@ -0,0 +1,7 @@
+CREATE TABLE rate_limits (
+  endpoint INTEGER NOT NULL REFERENCES endpoints(id) ON DELETE RESTRICT ON UPDATE CASCADE,
+  token INTEGER NOT NULL REFERENCES tokens(id) ON DELETE CASCADE,
+  remaining INTEGER NOT NULL,
+  reset INTEGER NOT NULL,
+  PRIMARY KEY (endpoint, token)
+);
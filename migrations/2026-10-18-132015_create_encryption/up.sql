@@ -0,0 +1,5 @@
+CREATE TABLE encryption (
+  id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+  salt TEXT NOT NULL,
+  verifier TEXT NOT NULL
+);
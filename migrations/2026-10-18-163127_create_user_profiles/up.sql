@@ -0,0 +1,16 @@
+CREATE TABLE user_profiles (
+  user INTEGER NOT NULL PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  screen_name TEXT NOT NULL,
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  location TEXT NOT NULL,
+  url TEXT,
+  created_at INTEGER NOT NULL,
+  followers_count INTEGER NOT NULL,
+  friends_count INTEGER NOT NULL,
+  statuses_count INTEGER NOT NULL,
+  protected BOOLEAN NOT NULL,
+  verified BOOLEAN NOT NULL,
+  default_profile_image BOOLEAN NOT NULL,
+  updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
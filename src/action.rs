@@ -0,0 +1,70 @@
+use diesel::{dsl::*, prelude::*};
+use futures::future::{FutureExt, LocalBoxFuture};
+
+use crate::error::Error;
+use crate::schema::*;
+use crate::twitter;
+
+/// An action to take on a user, which is queued in `pending_actions` and taken by
+/// `cmd::followers::blocker`.
+#[derive(Clone, Copy)]
+pub enum Action {
+    Block,
+    Unblock,
+    Mute,
+}
+
+impl Action {
+    /// Returns the name of the action, which is stored in `pending_actions`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Block => "block",
+            Action::Unblock => "unblock",
+            Action::Mute => "mute",
+        }
+    }
+
+    /// Returns the URI of the endpoint to take the action.
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            Action::Block => twitter::BLOCKS_CREATE,
+            Action::Unblock => twitter::BLOCKS_DESTROY,
+            Action::Mute => twitter::MUTES_USERS_CREATE,
+        }
+    }
+
+    /// Takes the action on `user` with the token of `permit`, which is held until the response
+    /// arrives.
+    pub fn send<'a>(
+        self,
+        client: &'a twitter::Client,
+        permit: twitter::Permit<'a>,
+        user: i64,
+    ) -> LocalBoxFuture<'a, Result<(), Error>> {
+        match self {
+            Action::Block => client.blocks_create(permit, user).boxed_local(),
+            Action::Unblock => client.blocks_destroy(permit, user).boxed_local(),
+            Action::Mute => client.mutes_users_create(permit, user).boxed_local(),
+        }
+    }
+
+    /// Records the result of a successful action to the database.
+    pub fn record(self, auth: i64, user: i64, conn: &SqliteConnection) -> QueryResult<()> {
+        match self {
+            Action::Block => {
+                insert_or_ignore_into(blocks::table)
+                    .values((blocks::source.eq(auth), blocks::target.eq(user)))
+                    .execute(conn)?;
+            }
+            Action::Unblock => {
+                delete(blocks::table.find((auth, user))).execute(conn)?;
+            }
+            Action::Mute => {
+                insert_or_ignore_into(mutes::table)
+                    .values((mutes::source.eq(auth), mutes::target.eq(user)))
+                    .execute(conn)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
-use oauth;
-
+#[derive(Clone)]
 pub struct Token {
+    /// ID of the `tokens` row.
+    pub id: i32,
+    /// ID of the user who authorized the token.
+    pub user: i64,
     pub client: oauth::Credentials<Box<str>>,
     pub token: oauth::Credentials<Box<str>>,
 }
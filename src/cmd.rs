@@ -1,3 +1,11 @@
+pub mod accounts;
 pub mod authorize;
+pub mod blocks;
 pub mod default;
 pub mod followers;
+pub mod friends;
+pub mod list_members;
+pub mod list_subscribers;
+pub mod rekey;
+pub mod tweet;
+pub mod unblock;
@@ -0,0 +1,338 @@
+use std::collections::BTreeSet;
+use std::slice;
+
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::{dsl::*, prelude::*, sql_query};
+use structopt::StructOpt;
+
+use crate::common::connect_database;
+use crate::crypto::KeyOpts;
+use crate::error::Error;
+use crate::query;
+use crate::schema::*;
+use crate::store;
+use crate::twitter;
+
+#[derive(StructOpt)]
+pub enum Opts {
+    #[structopt(about = "List the users who have authorized tokens")]
+    List(ListOpts),
+    #[structopt(about = "Check that the stored tokens are still valid")]
+    Verify(VerifyOpts),
+    #[structopt(about = "Invalidate tokens with the API and remove them from the database")]
+    Revoke(RevokeOpts),
+    #[structopt(about = "Remove tokens from the database")]
+    Remove(RemoveOpts),
+}
+
+#[derive(StructOpt)]
+pub struct ListOpts {
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+}
+
+#[derive(StructOpt)]
+pub struct VerifyOpts {
+    /// User IDs of the users whose tokens to verify (all the users by default)
+    users: Vec<i64>,
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+    #[structopt(flatten)]
+    api: twitter::ApiOpts,
+    #[structopt(flatten)]
+    key: KeyOpts,
+}
+
+#[derive(StructOpt)]
+pub struct RemoveOpts {
+    /// User IDs of the users whose tokens to remove
+    #[structopt(required_unless = "token")]
+    users: Vec<i64>,
+    /// ID of a token to remove, as shown by `accounts verify` (can be specified multiple times)
+    #[structopt(long, number_of_values = 1)]
+    token: Vec<i32>,
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+}
+
+#[derive(StructOpt)]
+pub struct RevokeOpts {
+    #[structopt(flatten)]
+    remove: RemoveOpts,
+    #[structopt(flatten)]
+    api: twitter::ApiOpts,
+    #[structopt(flatten)]
+    key: KeyOpts,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    match opts {
+        Opts::List(opts) => list(opts),
+        Opts::Verify(opts) => verify(opts).await,
+        Opts::Revoke(opts) => {
+            let http = opts.api.http();
+            remove(opts.remove, Some((&opts.key, &http))).await
+        }
+        Opts::Remove(opts) => remove(opts, None).await,
+    }
+}
+
+fn list(opts: ListOpts) -> Result<(), Error> {
+    #[derive(QueryableByName)]
+    struct QueriedAccount {
+        #[sql_type = "BigInt"]
+        id: i64,
+        #[sql_type = "Nullable<Text>"]
+        screen_name: Option<String>,
+        #[sql_type = "BigInt"]
+        tokens: i64,
+    }
+
+    let conn = connect_database(&opts.database)?;
+    let default = query::default_user(&conn)?;
+
+    let accounts = sql_query(
+        "SELECT users.id AS id, user_profiles.screen_name AS screen_name, COUNT(*) AS tokens \
+         FROM tokens \
+         JOIN users ON tokens.user = users.id \
+         LEFT JOIN user_profiles ON user_profiles.user = users.id \
+         GROUP BY users.id \
+         ORDER BY users.id",
+    )
+    .load::<QueriedAccount>(&conn)?;
+
+    for account in accounts {
+        let marker = if Some(account.id) == default {
+            '*'
+        } else {
+            ' '
+        };
+        let screen_name = account
+            .screen_name
+            .map(|name| format!("@{}", name))
+            .unwrap_or_else(|| "-".to_owned());
+        println!(
+            "{} {}\t{}\t{} token{}",
+            marker,
+            account.id,
+            screen_name,
+            account.tokens,
+            if account.tokens == 1 { "" } else { "s" },
+        );
+    }
+
+    Ok(())
+}
+
+async fn verify(opts: VerifyOpts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+    let cipher = opts.key.cipher(&conn)?;
+    let http = opts.api.http();
+
+    let users = if opts.users.is_empty() {
+        query::accounts(&conn)?
+    } else {
+        opts.users
+    };
+
+    let mut dead = 0;
+    for user in users {
+        let tokens = query::credentials(user, cipher.as_ref(), &conn)?;
+        if tokens.is_empty() {
+            return Err(Error::NoCredentials(user));
+        }
+
+        for token in tokens {
+            let id = token.id;
+            let client = twitter::Client::new(vec![token], http.clone(), 1);
+            match client.verify_credentials().await {
+                Ok(account) => {
+                    if account.id != user {
+                        log::warn!(
+                            "Token {} is stored for user {} but belongs to user {}",
+                            id,
+                            user,
+                            account.id,
+                        );
+                    }
+                    query::save_profiles(slice::from_ref(&account), &conn)?;
+                    println!(
+                        "User {} (token {}): valid (@{})",
+                        user, id, account.screen_name
+                    );
+                }
+                Err(Error::Account { error, .. }) => {
+                    dead += 1;
+                    println!("User {} (token {}): DEAD: {}", user, id, error);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    if dead > 0 {
+        log::warn!(
+            "{} tokens are unusable; remove them with `accounts remove --token <id>`",
+            dead,
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the specified tokens, invalidating them with the API first if `revoke` gives the key to
+/// decrypt their secrets and the client to send the requests with.
+async fn remove(opts: RemoveOpts, revoke: Option<(&KeyOpts, &twitter::Http)>) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+
+    let mut ids: BTreeSet<i32> = opts.token.iter().copied().collect();
+    for &user in &opts.users {
+        let tokens = tokens::table
+            .select(tokens::id)
+            .filter(tokens::user.eq(user))
+            .load::<i32>(&conn)?;
+        if tokens.is_empty() {
+            return Err(Error::NoCredentials(user));
+        }
+        ids.extend(tokens);
+    }
+    for &id in &opts.token {
+        if tokens::table
+            .find(id)
+            .select(tokens::id)
+            .first::<i32>(&conn)
+            .optional()?
+            .is_none()
+        {
+            return Err(Error::Input(format!("token not found: {}", id)));
+        }
+    }
+
+    if let Some((key, http)) = revoke {
+        let cipher = key.cipher(&conn)?;
+        let users: BTreeSet<i64> = tokens::table
+            .select(tokens::user)
+            .filter(tokens::id.eq_any(&ids))
+            .load(&conn)?
+            .into_iter()
+            .collect();
+        for user in users {
+            for token in query::credentials(user, cipher.as_ref(), &conn)? {
+                let id = token.id;
+                if !ids.contains(&id) {
+                    continue;
+                }
+                let client = twitter::Client::new(vec![token], http.clone(), 1);
+                match client.invalidate_token().await {
+                    Ok(()) => log::info!("Invalidated token {} of user {}", id, user),
+                    // The token is already unusable.
+                    Err(Error::Account { error, .. }) => {
+                        log::info!("Token {} of user {} is unusable: {}", id, user, error)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    let removed = conn.transaction::<_, Error, _>(|| {
+        let rows = tokens::table
+            .select((tokens::client, tokens::token, tokens::user))
+            .filter(tokens::id.eq_any(&ids))
+            .load::<(i32, i32, i64)>(&conn)?;
+
+        // Foreign key constraints are not enforced, so `ON DELETE CASCADE` does not apply. The IDs
+        // of the tokens are reused, so the rate limits would otherwise be taken over by new tokens.
+        delete(rate_limits::table.filter(rate_limits::token.eq_any(&ids))).execute(&conn)?;
+        delete(tokens::table.filter(tokens::id.eq_any(&ids))).execute(&conn)?;
+
+        // `tokens.client` restricts the deletion of the credentials, so delete only the ones no
+        // longer referenced by any token.
+        let mut removed = Vec::new();
+        let credentials: BTreeSet<i32> = rows
+            .iter()
+            .flat_map(|&(client, token, _)| vec![client, token])
+            .collect();
+        for id in credentials {
+            let referenced = select(exists(
+                tokens::table.filter(tokens::client.eq(id).or(tokens::token.eq(id))),
+            ))
+            .get_result::<bool>(&conn)?;
+            if !referenced {
+                removed.push(
+                    credentials::table
+                        .find(id)
+                        .select((credentials::identifier, credentials::secret))
+                        .get_result::<(String, String)>(&conn)?,
+                );
+                delete(credentials::table.find(id)).execute(&conn)?;
+            }
+        }
+
+        // `default_user.user` restricts the deletion of the user, so unset the default user if
+        // it has no tokens left. The `users` row is kept for the blocks retrieved for the user.
+        let users: BTreeSet<i64> = rows.iter().map(|&(_, _, user)| user).collect();
+        for user in users {
+            let has_tokens = select(exists(tokens::table.filter(tokens::user.eq(user))))
+                .get_result::<bool>(&conn)?;
+            if !has_tokens {
+                let n = delete(default_user::table.filter(default_user::user.eq(user)))
+                    .execute(&conn)?;
+                if n > 0 {
+                    log::info!("Unset the default user {}", user);
+                }
+            }
+        }
+
+        Ok(removed)
+    })?;
+
+    // Delete the secrets held outside the database after the removal has been committed.
+    for (identifier, secret) in &removed {
+        if let Err(e) = store::store_of(secret, None).delete(identifier) {
+            log::warn!("Unable to delete the secret of {}: {}", identifier, e);
+        }
+    }
+
+    log::info!(
+        "Removed {} tokens and {} credentials",
+        ids.len(),
+        removed.len(),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Database;
+
+    #[tokio::test]
+    async fn remove_rate_limits() {
+        let db = Database::new("remove_rate_limits");
+        let tokens = db.authorize(1, 1);
+        let endpoint = query::endpoint(twitter::FOLLOWERS_IDS, &db.conn).unwrap();
+        insert_into(rate_limits::table)
+            .values((
+                rate_limits::endpoint.eq(endpoint),
+                rate_limits::token.eq(tokens[0].id),
+                rate_limits::remaining.eq(0),
+                rate_limits::reset.eq(i64::MAX),
+            ))
+            .execute(&db.conn)
+            .unwrap();
+
+        let opts = RemoveOpts::from_iter(&["test", "--database", db.path(), "1"]);
+        remove(opts, None).await.unwrap();
+
+        // A token authorized later with the same ID does not inherit the rate limits.
+        let tokens = tokens::table.count().get_result::<i64>(&db.conn);
+        assert_eq!(tokens.unwrap(), 0);
+        let rate_limits = rate_limits::table.count().get_result::<i64>(&db.conn);
+        assert_eq!(rate_limits.unwrap(), 0);
+    }
+}
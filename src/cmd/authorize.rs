@@ -1,12 +1,22 @@
-use std::io::{stdin, stdout, BufRead, Write};
+use std::io::{self, stdin, stdout, BufRead, Write};
+use std::net::Ipv4Addr;
+use std::slice;
+use std::time::Duration;
 
 use diesel::{dsl::*, prelude::*};
-use oauth;
-use reqwest::{self, header::AUTHORIZATION};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize};
 use structopt::StructOpt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 
+use crate::auth::Token;
 use crate::common::connect_database;
+use crate::crypto::KeyOpts;
+use crate::error::Error;
+use crate::query;
 use crate::schema::*;
+use crate::store::StoreOpts;
 use crate::twitter;
 
 #[derive(StructOpt)]
@@ -14,13 +24,32 @@ pub struct Opts {
     /// Path to the database
     #[structopt(long, default_value = "db.sqlite3")]
     database: String,
-    /// Do not check validity of the credentials
+    /// Do not check validity of the credentials nor record the profile of the user
     #[structopt(short, long)]
     no_verify: bool,
+    /// Sign in with the PIN-based OAuth flow instead of entering an access token
+    #[structopt(long)]
+    pin: bool,
+    /// Sign in with the OAuth flow, receiving the callback on a local HTTP listener instead of
+    /// entering an access token. `http://127.0.0.1/callback` (with the port if specified) must be
+    /// registered as a callback URL of the app
+    #[structopt(long, conflicts_with = "pin")]
+    callback: bool,
+    /// Port of the local HTTP listener of `--callback` (an available port is used by default)
+    #[structopt(long, requires = "callback")]
+    port: Option<u16>,
+    #[structopt(flatten)]
+    api: twitter::ApiOpts,
+    #[structopt(flatten)]
+    key: KeyOpts,
+    #[structopt(flatten)]
+    store: StoreOpts,
 }
 
-pub async fn run(opts: Opts) {
-    let conn = connect_database(&opts.database).unwrap();
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+    let cipher = opts.key.cipher(&conn)?;
+    let http = opts.api.http();
 
     let stdin_isatty = atty::is(atty::Stream::Stdin);
     let stdin = stdin();
@@ -31,11 +60,8 @@ pub async fn run(opts: Opts) {
     macro_rules! gets {
         () => {
             match lines.next() {
-                Some(s) => s.unwrap(),
-                None => {
-                    eprintln!("Unexpected end of input");
-                    return;
-                }
+                Some(s) => s?,
+                None => return Err(Error::Input("unexpected end of input".to_owned())),
             }
         };
     }
@@ -43,8 +69,8 @@ pub async fn run(opts: Opts) {
     macro_rules! prompt {
         ($($args:tt)*) => {
             if stdin_isatty {
-                write!(stdout, $($args)*).unwrap();
-                stdout.flush().unwrap();
+                write!(stdout, $($args)*)?;
+                stdout.flush()?;
             }
         };
     }
@@ -55,93 +81,289 @@ pub async fn run(opts: Opts) {
     prompt!("Consumer secret: ");
     let consumer_secret = gets!();
 
-    prompt!("Access token: ");
-    let access_token = gets!();
-    let user: i64 = if let Ok(id) = access_token.split('-').next().unwrap().parse() {
-        id
-    } else {
-        eprintln!("Unrecognized token format");
-        return;
+    let client = oauth::Credentials {
+        identifier: &*consumer_key,
+        secret: &*consumer_secret,
     };
 
-    prompt!("Access token secret: ");
-    let token_secret = gets!();
+    let (user, access_token, token_secret) = if opts.pin || opts.callback {
+        // Listener to receive the redirect from the authorization page
+        let mut listener = if opts.callback {
+            Some(TcpListener::bind((Ipv4Addr::LOCALHOST, opts.port.unwrap_or(0))).await?)
+        } else {
+            None
+        };
+        let callback = if let Some(ref listener) = listener {
+            format!("http://{}/callback", listener.local_addr()?)
+        } else {
+            "oob".to_owned()
+        };
 
-    if !opts.no_verify {
-        write!(stdout, "Verifying the credentials... ").unwrap();
+        let request_token = request_token(client, &callback, &http).await?;
+        writeln!(
+            stdout,
+            "Open the following URL in your browser and authorize the app:\n{}?oauth_token={}",
+            http.uri(twitter::OAUTH_AUTHORIZE),
+            request_token.oauth_token,
+        )?;
 
-        let client = oauth::Credentials {
-            identifier: &*consumer_key,
-            secret: &*consumer_secret,
+        let verifier = if let Some(ref mut listener) = listener {
+            writeln!(stdout, "Waiting for the authorization at {}", callback)?;
+            receive_verifier(listener, &request_token.oauth_token).await?
+        } else {
+            prompt!("PIN: ");
+            gets!().trim().to_owned()
         };
         let token = oauth::Credentials {
-            identifier: &*access_token,
-            secret: &*token_secret,
+            identifier: &*request_token.oauth_token,
+            secret: &*request_token.oauth_token_secret,
         };
+        let access_token = access_token(client, token, &verifier, &http).await?;
+        writeln!(
+            stdout,
+            "Authorized as @{} (user ID: {})",
+            access_token.screen_name, access_token.user_id,
+        )?;
+
+        (
+            access_token.user_id,
+            access_token.oauth_token,
+            access_token.oauth_token_secret,
+        )
+    } else {
+        prompt!("Access token: ");
+        let access_token = gets!();
+        let user: i64 = if let Ok(id) = access_token.split('-').next().unwrap().parse() {
+            id
+        } else {
+            return Err(Error::OAuth("unrecognized token format".to_owned()));
+        };
+
+        prompt!("Access token secret: ");
+        let token_secret = gets!();
 
-        let oauth::Request {
-            authorization,
-            data: uri,
-        } = oauth::Builder::new(client, oauth::HmacSha1)
-            .token(token)
-            .get(
-                twitter::ACCOUNT_VERIFY_CREDENTIALS,
-                twitter::AccountVerifyCredentials {
-                    include_entities: false,
-                    skip_status: false,
-                    include_email: false,
-                },
-            );
-        let response = reqwest::Client::new()
-            .get(&uri)
-            .header(AUTHORIZATION, authorization)
-            .send()
+        (user, access_token, token_secret)
+    };
+
+    // Retrieve the profile of the user, which also verifies the credentials entered by hand (the
+    // OAuth flow has verified them by itself).
+    let profile = if opts.no_verify {
+        None
+    } else {
+        write!(stdout, "Verifying the credentials... ")?;
+
+        let token = Token {
+            // The token has not been stored yet.
+            id: 0,
+            user,
+            client: oauth::Credentials {
+                identifier: consumer_key.as_str().into(),
+                secret: consumer_secret.as_str().into(),
+            },
+            token: oauth::Credentials {
+                identifier: access_token.as_str().into(),
+                secret: token_secret.as_str().into(),
+            },
+        };
+        let profile = match twitter::Client::new(vec![token], http.clone(), 1)
+            .verify_credentials()
             .await
-            .unwrap();
+        {
+            Ok(profile) => profile,
+            Err(e) => {
+                writeln!(stdout)?;
+                eprintln!("Unable to verify the credentials");
+                return Err(e);
+            }
+        };
 
-        if !response.status().is_success() {
-            writeln!(stdout, "").unwrap();
-            eprintln!("Unable to verify the credentials");
-            return;
-        }
+        writeln!(stdout, "Success")?;
+        Some(profile)
+    };
 
-        writeln!(stdout, "Success").unwrap();
-    }
+    let store = opts.store.store(cipher.as_ref());
+    let consumer_secret = store.put(&consumer_key, &consumer_secret)?;
+    let token_secret = store.put(&access_token, &token_secret)?;
 
     insert_or_ignore_into(users::table)
         .values(users::id.eq(user))
-        .execute(&conn)
-        .unwrap();
+        .execute(&conn)?;
+    if let Some(ref profile) = profile {
+        query::save_profiles(slice::from_ref(profile), &conn)?;
+    }
     insert_or_ignore_into(credentials::table)
         .values((
             credentials::identifier.eq(&consumer_key),
             credentials::secret.eq(&consumer_secret),
         ))
-        .execute(&conn)
-        .unwrap();
+        .execute(&conn)?;
     insert_or_ignore_into(credentials::table)
         .values((
             credentials::identifier.eq(&access_token),
             credentials::secret.eq(&token_secret),
         ))
-        .execute(&conn)
-        .unwrap();
+        .execute(&conn)?;
     let client: i32 = credentials::table
         .select(credentials::id)
         .filter(credentials::identifier.eq(&consumer_key))
-        .get_result(&conn)
-        .unwrap();
+        .get_result(&conn)?;
     let token: i32 = credentials::table
         .select(credentials::id)
         .filter(credentials::identifier.eq(&access_token))
-        .get_result(&conn)
-        .unwrap();
+        .get_result(&conn)?;
     insert_or_ignore_into(tokens::table)
         .values((
             tokens::client.eq(client),
             tokens::token.eq(token),
             tokens::user.eq(user),
         ))
-        .execute(&conn)
-        .unwrap();
+        .execute(&conn)?;
+
+    Ok(())
+}
+
+/// Obtains temporary credentials with `oauth/request_token`.
+async fn request_token(
+    client: oauth::Credentials<&str>,
+    callback: &str,
+    http: &twitter::Http,
+) -> Result<twitter::RequestToken, Error> {
+    let request = oauth::Builder::new(client, oauth::HmacSha1)
+        .callback(callback)
+        .post_form(http.uri(twitter::OAUTH_REQUEST_TOKEN), ());
+    post_form(twitter::OAUTH_REQUEST_TOKEN, request, http).await
+}
+
+/// Exchanges the temporary credentials and the verifier for an access token with
+/// `oauth/access_token`.
+async fn access_token(
+    client: oauth::Credentials<&str>,
+    token: oauth::Credentials<&str>,
+    verifier: &str,
+    http: &twitter::Http,
+) -> Result<twitter::AccessToken, Error> {
+    let request = oauth::Builder::new(client, oauth::HmacSha1)
+        .token(token)
+        .verifier(verifier)
+        .post_form(http.uri(twitter::OAUTH_ACCESS_TOKEN), ());
+    post_form(twitter::OAUTH_ACCESS_TOKEN, request, http).await
+}
+
+/// Waits for the authorization page to redirect the browser to the callback URL served by
+/// `listener`, and returns the `oauth_verifier` for the temporary credentials `oauth_token`.
+///
+/// The connections are handled concurrently so that a stalled one does not block the others.
+async fn receive_verifier(listener: &mut TcpListener, oauth_token: &str) -> Result<String, Error> {
+    let mut connections = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (stream, _) = result?;
+                connections.push(handle_callback(stream, oauth_token));
+            }
+            Some(result) = connections.next(), if !connections.is_empty() => match result {
+                Ok(Some(result)) => return result,
+                Ok(None) => {}
+                Err(e) => log::warn!("Error on a connection to the callback listener: {}", e),
+            },
+        }
+    }
+}
+
+/// Reads a request to the callback listener and responds to it.
+///
+/// Returns the `oauth_verifier` (or an error if the authorization has been denied) if the request
+/// is the redirect for `oauth_token`, or `None` otherwise.
+async fn handle_callback(
+    mut stream: TcpStream,
+    oauth_token: &str,
+) -> io::Result<Option<Result<String, Error>>> {
+    // Time to wait for each read from the browser
+    const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[derive(Default, Deserialize)]
+    struct Callback {
+        oauth_token: Option<String>,
+        oauth_verifier: Option<String>,
+        denied: Option<String>,
+    }
+
+    // Read the request head, ignoring the body if any.
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+        let n = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buf))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out reading request"))??;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    // Request target of the request line (e.g. `/callback?oauth_token=...`)
+    let target = head
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .unwrap_or("");
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap();
+    let query = target.next().unwrap_or("");
+
+    if path != "/callback" {
+        respond(&mut stream, "404 Not Found", "Not found").await?;
+        return Ok(None);
+    }
+
+    let params: Callback = serde_urlencoded::from_str(query).unwrap_or_default();
+    if params.denied.is_some() {
+        respond(&mut stream, "200 OK", "The authorization has been denied.").await?;
+        let e = Error::OAuth("the authorization has been denied".to_owned());
+        return Ok(Some(Err(e)));
+    }
+    match (params.oauth_token, params.oauth_verifier) {
+        (Some(ref token), Some(verifier)) if token == oauth_token => {
+            let body = "The app has been authorized. You can close this page now.";
+            respond(&mut stream, "200 OK", body).await?;
+            Ok(Some(Ok(verifier)))
+        }
+        _ => {
+            respond(&mut stream, "400 Bad Request", "Bad request").await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Writes a plain text response to the browser.
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        body.len(),
+        body,
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+/// Sends a signed `POST` request to an OAuth endpoint and deserializes the form-encoded response.
+async fn post_form<T: DeserializeOwned>(
+    endpoint: &str,
+    request: oauth::Request,
+    http: &twitter::Http,
+) -> Result<T, Error> {
+    let response = http.post_form(endpoint, request).await?;
+
+    if !response.status.is_success() {
+        let e = twitter::ApiError::from_response(&response);
+        return Err(Error::Api(e));
+    }
+
+    serde_urlencoded::from_bytes(&response.body)
+        .map_err(|e| Error::OAuth(format!("malformed response from {}: {}", endpoint, e)))
 }
@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, stdout, Write};
+use std::str::FromStr;
+
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::common::{connect_database, format_date, parse_date};
+use crate::error::Error;
+use crate::query;
+use crate::schema::*;
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// Blocks to list: `blocked-by` (users who block you), `blocking` (users you block), `mutual`
+    /// (users who block you and whom you block) or `all`
+    #[structopt(
+        long,
+        default_value = "all",
+        possible_values = &["all", "blocked-by", "blocking", "mutual"],
+    )]
+    direction: Direction,
+    /// User ID of the user whose blocks to list (can be specified multiple times; the default
+    /// user by default)
+    #[structopt(long, number_of_values = 1)]
+    login: Vec<i64>,
+    /// List the blocks of all the users who have authorized the app
+    #[structopt(long, conflicts_with = "login")]
+    all_accounts: bool,
+    /// Only list the blocks retrieved before the date (Unix time or `YYYY-MM-DD`)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    before: Option<i64>,
+    /// Only list the blocks retrieved at or after the date (Unix time or `YYYY-MM-DD`)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    after: Option<i64>,
+    /// Output format: `table`, `csv`, `json` (an array of objects) or `jsonl` (an object per
+    /// line)
+    #[structopt(
+        long,
+        default_value = "table",
+        possible_values = &["table", "csv", "json", "jsonl"],
+    )]
+    format: Format,
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    All,
+    BlockedBy,
+    Blocking,
+    Mutual,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Table,
+    Csv,
+    Json,
+    JsonLines,
+}
+
+/// A `blocks` row between an authenticated user and another user.
+#[derive(Serialize)]
+struct Row {
+    authenticated_user: i64,
+    /// `blocked-by` if `user` blocks the authenticated user, or `blocking` if the authenticated
+    /// user blocks `user`.
+    direction: &'static str,
+    user: i64,
+    /// Whether the block in the opposite direction has also been recorded.
+    mutual: bool,
+    retrieved_at: i64,
+    /// The profile of `user` if it has been retrieved.
+    #[serde(flatten)]
+    profile: Option<Profile>,
+}
+
+#[derive(Clone, Queryable, Serialize)]
+struct Profile {
+    #[serde(skip)]
+    user: i64,
+    screen_name: String,
+    name: String,
+    description: String,
+    location: String,
+    url: Option<String>,
+    created_at: i64,
+    followers_count: i64,
+    friends_count: i64,
+    statuses_count: i64,
+    protected: bool,
+    verified: bool,
+    default_profile_image: bool,
+    #[serde(rename = "profile_updated_at")]
+    updated_at: i64,
+}
+
+const CSV_HEADER: &[&str] = &[
+    "authenticated_user",
+    "direction",
+    "user",
+    "mutual",
+    "retrieved_at",
+    "screen_name",
+    "name",
+    "description",
+    "location",
+    "url",
+    "created_at",
+    "followers_count",
+    "friends_count",
+    "statuses_count",
+    "protected",
+    "verified",
+    "default_profile_image",
+    "profile_updated_at",
+];
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "all" => Ok(Direction::All),
+            "blocked-by" => Ok(Direction::BlockedBy),
+            "blocking" => Ok(Direction::Blocking),
+            "mutual" => Ok(Direction::Mutual),
+            _ => Err(format!("unknown direction: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+impl Opts {
+    /// Returns the `blocks` rows retrieved in the range of `--before` and `--after`.
+    fn blocks(&self) -> blocks::BoxedQuery<'static, Sqlite> {
+        let mut query = blocks::table
+            .order((blocks::retrieved_at, blocks::source, blocks::target))
+            .into_boxed();
+        if let Some(before) = self.before {
+            query = query.filter(blocks::retrieved_at.lt(before));
+        }
+        if let Some(after) = self.after {
+            query = query.filter(blocks::retrieved_at.ge(after));
+        }
+        query
+    }
+}
+
+pub fn run(opts: Opts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+    let rows = rows(&opts, &conn)?;
+
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    match opts.format {
+        Format::Table => write_table(&rows, &mut stdout)?,
+        Format::Csv => write_csv(&rows, &mut stdout)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &rows)?;
+            writeln!(stdout)?;
+        }
+        Format::JsonLines => {
+            for row in &rows {
+                serde_json::to_writer(&mut stdout, row)?;
+                writeln!(stdout)?;
+            }
+        }
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Returns the `blocks` rows of the accounts selected by `opts`, with the profiles joined.
+fn rows(opts: &Opts, conn: &SqliteConnection) -> Result<Vec<Row>, Error> {
+    let auths = if opts.all_accounts {
+        query::accounts(conn)?
+    } else if opts.login.is_empty() {
+        vec![query::authenticated_user(None, conn)?]
+    } else {
+        opts.login.clone()
+    };
+
+    let mut rows = Vec::new();
+    for auth in auths {
+        // Users blocked by the authenticated user regardless of the date range
+        let blocking: HashSet<i64> = blocks::table
+            .select(blocks::target)
+            .filter(blocks::source.eq(auth))
+            .load::<i64>(conn)?
+            .into_iter()
+            .collect();
+        // Users who block the authenticated user regardless of the date range
+        let blocked_by: HashSet<i64> = blocks::table
+            .select(blocks::source)
+            .filter(blocks::target.eq(auth))
+            .load::<i64>(conn)?
+            .into_iter()
+            .collect();
+
+        let mut account_rows = Vec::new();
+        if opts.direction != Direction::Blocking {
+            for (source, _, retrieved_at) in
+                opts.blocks()
+                    .filter(blocks::target.eq(auth))
+                    .load::<(i64, i64, i64)>(conn)?
+            {
+                let mutual = blocking.contains(&source);
+                if opts.direction == Direction::Mutual && !mutual {
+                    continue;
+                }
+                account_rows.push(Row {
+                    authenticated_user: auth,
+                    direction: "blocked-by",
+                    user: source,
+                    mutual,
+                    retrieved_at,
+                    profile: None,
+                });
+            }
+        }
+        if opts.direction == Direction::All || opts.direction == Direction::Blocking {
+            for (_, target, retrieved_at) in
+                opts.blocks()
+                    .filter(blocks::source.eq(auth))
+                    .load::<(i64, i64, i64)>(conn)?
+            {
+                account_rows.push(Row {
+                    authenticated_user: auth,
+                    direction: "blocking",
+                    user: target,
+                    mutual: blocked_by.contains(&target),
+                    retrieved_at,
+                    profile: None,
+                });
+            }
+        }
+        account_rows.sort_by_key(|row| (row.retrieved_at, row.user));
+        rows.extend(account_rows);
+    }
+
+    // Join the profiles, chunking the IDs to stay within the limit of bound parameters of SQLite.
+    let ids: Vec<i64> = rows
+        .iter()
+        .map(|row| row.user)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut profiles = HashMap::new();
+    for ids in ids.chunks(500) {
+        for profile in user_profiles::table
+            .filter(user_profiles::user.eq_any(ids))
+            .load::<Profile>(conn)?
+        {
+            profiles.insert(profile.user, profile);
+        }
+    }
+    for row in &mut rows {
+        row.profile = profiles.get(&row.user).cloned();
+    }
+
+    Ok(rows)
+}
+
+/// Writes the rows as a table aligned with spaces, for reading in the terminal.
+fn write_table(rows: &[Row], w: &mut impl Write) -> io::Result<()> {
+    let header = [
+        "ACCOUNT",
+        "DIRECTION",
+        "USER",
+        "SCREEN NAME",
+        "NAME",
+        "FOLLOWERS",
+        "RETRIEVED",
+    ];
+    let cells: Vec<[String; 7]> = rows
+        .iter()
+        .map(|row| {
+            let direction = if row.mutual {
+                format!("{} (mutual)", row.direction)
+            } else {
+                row.direction.to_owned()
+            };
+            let profile = row.profile.as_ref();
+            [
+                row.authenticated_user.to_string(),
+                direction,
+                row.user.to_string(),
+                profile.map_or_else(|| "-".to_owned(), |p| format!("@{}", p.screen_name)),
+                profile.map_or_else(|| "-".to_owned(), |p| p.name.clone()),
+                profile.map_or_else(|| "-".to_owned(), |p| p.followers_count.to_string()),
+                format_date(row.retrieved_at),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(|h| h.len());
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut write_line = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(w, "{}", line.trim_end())
+    };
+    write_line(&header)?;
+    for row in &cells {
+        let row: Vec<&str> = row.iter().map(String::as_str).collect();
+        write_line(&row)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the rows as CSV (RFC 4180) with a header line.
+fn write_csv(rows: &[Row], w: &mut impl Write) -> io::Result<()> {
+    fn field(s: &str) -> String {
+        if s.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    }
+
+    writeln!(w, "{}", CSV_HEADER.join(","))?;
+    for row in rows {
+        let mut fields = vec![
+            row.authenticated_user.to_string(),
+            row.direction.to_owned(),
+            row.user.to_string(),
+            row.mutual.to_string(),
+            row.retrieved_at.to_string(),
+        ];
+        match row.profile {
+            Some(ref p) => fields.extend(vec![
+                field(&p.screen_name),
+                field(&p.name),
+                field(&p.description),
+                field(&p.location),
+                p.url.as_deref().map(field).unwrap_or_default(),
+                p.created_at.to_string(),
+                p.followers_count.to_string(),
+                p.friends_count.to_string(),
+                p.statuses_count.to_string(),
+                p.protected.to_string(),
+                p.verified.to_string(),
+                p.default_profile_image.to_string(),
+                p.updated_at.to_string(),
+            ]),
+            None => fields.resize(CSV_HEADER.len(), String::new()),
+        }
+        writeln!(w, "{}", fields.join(","))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Database;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::from_iter(["test", "--login", "1"].iter().chain(args))
+    }
+
+    /// Records the `(source, target, retrieved_at)` blocks.
+    fn insert_blocks(db: &Database, values: &[(i64, i64, i64)]) {
+        let conn = &db.conn;
+        for &(source, target, retrieved_at) in values {
+            for &id in &[source, target] {
+                diesel::insert_or_ignore_into(users::table)
+                    .values(users::id.eq(id))
+                    .execute(conn)
+                    .unwrap();
+            }
+            diesel::insert_into(blocks::table)
+                .values((
+                    blocks::source.eq(source),
+                    blocks::target.eq(target),
+                    blocks::retrieved_at.eq(retrieved_at),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    }
+
+    fn summary(rows: &[Row]) -> Vec<(&'static str, i64, bool)> {
+        rows.iter()
+            .map(|row| (row.direction, row.user, row.mutual))
+            .collect()
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            user: 2,
+            screen_name: "user2".to_owned(),
+            name: "Doe, John".to_owned(),
+            description: "Say \"hi\"".to_owned(),
+            location: "Line 1\nLine 2".to_owned(),
+            url: None,
+            created_at: 1539202764,
+            followers_count: 1,
+            friends_count: 2,
+            statuses_count: 3,
+            protected: false,
+            verified: false,
+            default_profile_image: true,
+            updated_at: 1600000000,
+        }
+    }
+
+    fn row(profile: Option<Profile>) -> Row {
+        Row {
+            authenticated_user: 1,
+            direction: "blocked-by",
+            user: 2,
+            mutual: false,
+            retrieved_at: 100,
+            profile,
+        }
+    }
+
+    #[test]
+    fn mutual_direction() {
+        let db = Database::new("blocks_mutual_direction");
+        insert_blocks(&db, &[(2, 1, 100), (1, 2, 200), (3, 1, 300), (1, 4, 400)]);
+
+        let rows = super::rows(&opts(&[]), &db.conn).unwrap();
+        assert_eq!(
+            summary(&rows),
+            [
+                ("blocked-by", 2, true),
+                ("blocking", 2, true),
+                ("blocked-by", 3, false),
+                ("blocking", 4, false),
+            ],
+        );
+
+        let rows = super::rows(&opts(&["--direction", "mutual"]), &db.conn).unwrap();
+        assert_eq!(summary(&rows), [("blocked-by", 2, true)]);
+    }
+
+    #[test]
+    fn date_range() {
+        let db = Database::new("blocks_date_range");
+        insert_blocks(&db, &[(2, 1, 100), (3, 1, 200), (4, 1, 300)]);
+
+        // `--after` is inclusive while `--before` is exclusive.
+        let opts = opts(&["--after", "200", "--before", "300"]);
+        let rows = super::rows(&opts, &db.conn).unwrap();
+        assert_eq!(summary(&rows), [("blocked-by", 3, false)]);
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let mut csv = Vec::new();
+        write_csv(&[row(Some(profile()))], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.splitn(2, '\n');
+        assert_eq!(lines.next(), Some(&*CSV_HEADER.join(",")));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "1,blocked-by,2,false,100,user2,\"Doe, John\",\"Say \"\"hi\"\"\",\
+                 \"Line 1\nLine 2\",,1539202764,1,2,3,false,false,true,1600000000\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn csv_pads_missing_profile() {
+        let mut csv = Vec::new();
+        write_csv(&[row(None)], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let line = csv.lines().nth(1).unwrap();
+        assert_eq!(line, format!("1,blocked-by,2,false,100{}", ",".repeat(13)));
+        assert_eq!(line.split(',').count(), CSV_HEADER.len());
+    }
+}
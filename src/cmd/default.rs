@@ -2,6 +2,7 @@ use diesel::{dsl::*, prelude::*};
 use structopt::StructOpt;
 
 use crate::common::connect_database;
+use crate::error::Error;
 use crate::schema::*;
 
 #[derive(StructOpt)]
@@ -12,15 +13,14 @@ pub struct Opts {
     database: String,
 }
 
-pub fn run(opts: Opts) {
-    let conn = connect_database(&opts.database).unwrap();
+pub fn run(opts: Opts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
 
-    conn.transaction::<_, diesel::result::Error, _>(|| {
+    conn.transaction::<_, Error, _>(|| {
         delete(default_user::table).execute(&conn)?;
         insert_into(default_user::table)
             .values(default_user::user.eq(opts.user))
             .execute(&conn)?;
         Ok(())
     })
-    .unwrap();
 }
@@ -1,300 +1,1260 @@
-use std::{collections::VecDeque, future::Future, task::Poll};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::task::Poll;
+use std::time::Duration;
 
 use diesel::{dsl::*, prelude::*};
 use futures::{stream::FuturesUnordered, FutureExt, Stream, StreamExt};
-use oauth;
-use reqwest::{header::AUTHORIZATION, StatusCode};
+use rand::Rng;
+use reqwest::StatusCode;
 use std::marker::Unpin;
 use structopt::StructOpt;
-use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
-use crate::common::{connect_database, instant_to_epoch, wait_until};
+use crate::action::Action;
+use crate::auth::Token;
+use crate::common::{connect_database, wait_until};
+use crate::crypto::KeyOpts;
+use crate::error::Error;
 use crate::query;
 use crate::schema::*;
-use crate::twitter;
+use crate::twitter::{self, ErrorKind, Fetch, ListRef};
 
 #[derive(StructOpt)]
 pub struct Opts {
     /// User IDs of the users to search followers of
     users: Vec<i64>,
-    /// User ID of the user to authorize
-    #[structopt(long)]
-    login: Option<i64>,
+    /// How to retrieve the followers: `list` uses `followers/list` (200 users per request), and
+    /// `ids` uses `followers/ids` and `users/lookup` (5000 users per request)
+    #[structopt(long, default_value = "list", possible_values = &["list", "ids"])]
+    strategy: Strategy,
+    #[structopt(flatten)]
+    search: SearchOpts,
+}
+
+#[derive(Clone, Copy)]
+enum Strategy {
+    List,
+    Ids,
+}
+
+/// Options shared by the subcommands that search lists of users.
+#[derive(StructOpt)]
+pub struct SearchOpts {
+    /// User ID of the user to search on behalf of (can be specified multiple times)
+    #[structopt(long, number_of_values = 1)]
+    login: Vec<i64>,
+    /// Search on behalf of all the users who have authorized the app
+    #[structopt(long, conflicts_with = "login")]
+    all_accounts: bool,
     /// Path to the database
     #[structopt(long, default_value = "db.sqlite3")]
     database: String,
-    /// Do not block the users
+    /// Action to take on the users who block you
+    #[structopt(long, default_value = "block", possible_values = &["block", "mute", "none"])]
+    action: SearchAction,
+    /// Do not block the users (same as `--action none`)
     #[structopt(short, long)]
     no_block: bool,
     /// Search from the beginning instead of resuming
     #[structopt(long)]
     reset: bool,
+    /// User ID of a helper account whose tokens are used to enumerate user IDs with the `ids`
+    /// endpoints (can be specified multiple times), which is only supported by `followers
+    /// --strategy ids` and `tweet`. The users are still looked up and blocked with the tokens of
+    /// the authenticated user, since `blocked_by` is relative to it
+    #[structopt(long, number_of_values = 1)]
+    scan_with: Vec<i64>,
+    #[structopt(flatten)]
+    api: twitter::ApiOpts,
+    #[structopt(flatten)]
+    key: KeyOpts,
+    #[structopt(flatten)]
+    blocker: BlockerOpts,
+}
+
+/// Options of `blocker`.
+#[derive(StructOpt)]
+pub struct BlockerOpts {
+    /// Maximum number of attempts to take an action on a user before giving up
+    #[structopt(long, default_value = "5")]
+    max_attempts: u32,
+    /// Retry the actions given up by previous runs
+    #[structopt(long)]
+    retry_failed: bool,
+    /// Maximum number of concurrent requests to the API
+    #[structopt(long, default_value = "8")]
+    concurrency: usize,
+}
+
+impl BlockerOpts {
+    /// Creates a client for `tokens`, whose rate limits are shared by the requests of a run.
+    pub fn client(&self, tokens: Vec<Token>, http: &twitter::Http) -> twitter::Client {
+        twitter::Client::new(tokens, http.clone(), self.concurrency)
+    }
+
+    /// Creates a client for `tokens` sharing the rate limits of the tokens with `client`.
+    fn share(&self, client: &twitter::Client, tokens: Vec<Token>) -> twitter::Client {
+        client.share(tokens, self.concurrency)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SearchAction {
+    Block,
+    Mute,
+    None,
+}
+
+impl SearchOpts {
+    fn action(&self) -> Option<Action> {
+        match self.action {
+            _ if self.no_block => None,
+            SearchAction::Block => Some(Action::Block),
+            SearchAction::Mute => Some(Action::Mute),
+            SearchAction::None => None,
+        }
+    }
+}
+
+impl FromStr for SearchAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "block" => Ok(SearchAction::Block),
+            "mute" => Ok(SearchAction::Mute),
+            "none" => Ok(SearchAction::None),
+            _ => Err(format!("unknown action: {}", s)),
+        }
+    }
+}
+
+/// A paginated list of users to be searched for users who block you.
+#[derive(Clone)]
+pub enum UserList {
+    /// A list of user objects, which is retrieved on behalf of each account since `blocked_by`
+    /// is relative to the authenticated user.
+    Full(FullList),
+    /// A list of user IDs, which is retrieved once and looked up with `users/lookup` on behalf
+    /// of each account.
+    Ids(IdList),
+}
+
+/// A paginated list of user objects.
+#[derive(Clone)]
+pub enum FullList {
+    /// Followers of the user.
+    Followers(i64),
+    /// Users the user is following.
+    Friends(i64),
+    /// Members of the Twitter List.
+    ListMembers(ListRef),
+    /// Subscribers of the Twitter List.
+    ListSubscribers(ListRef),
+}
+
+/// A paginated list of user IDs.
+#[derive(Clone)]
+pub enum IdList {
+    /// Followers of the user.
+    Followers(i64),
+    /// Users who retweeted the tweet.
+    ///
+    /// The API v1.1 has no endpoint to list users who liked a tweet, so they cannot be searched.
+    Retweeters(i64),
+}
+
+impl UserList {
+    /// Returns the URI of the endpoint to retrieve the list.
+    fn endpoint(&self) -> &'static str {
+        match *self {
+            UserList::Full(FullList::Followers(_)) => twitter::FOLLOWERS_LIST,
+            UserList::Full(FullList::Friends(_)) => twitter::FRIENDS_LIST,
+            UserList::Full(FullList::ListMembers(_)) => twitter::LISTS_MEMBERS,
+            UserList::Full(FullList::ListSubscribers(_)) => twitter::LISTS_SUBSCRIBERS,
+            UserList::Ids(IdList::Followers(_)) => twitter::FOLLOWERS_IDS,
+            UserList::Ids(IdList::Retweeters(_)) => twitter::STATUSES_RETWEETERS_IDS,
+        }
+    }
+
+    /// Returns the ID of the object whose users are listed (a user, a Twitter List or a tweet),
+    /// which is used as the key of `user_list_cursors`.
+    ///
+    /// Returns `None` if the list is a Twitter List referred to by its slug, whose ID has to be
+    /// retrieved with `lists/show`.
+    fn id(&self) -> Option<i64> {
+        match *self {
+            UserList::Full(FullList::Followers(id))
+            | UserList::Full(FullList::Friends(id))
+            | UserList::Ids(IdList::Followers(id))
+            | UserList::Ids(IdList::Retweeters(id)) => Some(id),
+            UserList::Full(FullList::ListMembers(ref list))
+            | UserList::Full(FullList::ListSubscribers(ref list)) => match *list {
+                ListRef::Id(id) => Some(id),
+                ListRef::Slug { .. } => None,
+            },
+        }
+    }
+}
+
+impl FullList {
+    /// Retrieves a page of the list.
+    async fn fetch(
+        &self,
+        cursor: i64,
+        client: &twitter::Client,
+    ) -> Result<Fetch<twitter::Users>, Error> {
+        match *self {
+            FullList::Followers(user_id) => client.followers_list(user_id, cursor).await,
+            FullList::Friends(user_id) => client.friends_list(user_id, cursor).await,
+            FullList::ListMembers(ref list) => client.lists_members(list, cursor).await,
+            FullList::ListSubscribers(ref list) => client.lists_subscribers(list, cursor).await,
+        }
+    }
 }
 
-pub async fn run(opts: Opts) {
-    let conn = connect_database(&opts.database).unwrap();
+impl IdList {
+    /// Retrieves a page of the list.
+    async fn fetch(
+        &self,
+        cursor: i64,
+        client: &twitter::Client,
+    ) -> Result<Fetch<twitter::Ids>, Error> {
+        match *self {
+            IdList::Followers(user_id) => client.followers_ids(user_id, cursor).await,
+            IdList::Retweeters(id) => client.statuses_retweeters_ids(id, cursor).await,
+        }
+    }
+}
+
+impl Display for UserList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UserList::Full(FullList::Followers(id)) | UserList::Ids(IdList::Followers(id)) => {
+                write!(f, "the followers of user {}", id)
+            }
+            UserList::Full(FullList::Friends(id)) => write!(f, "the friends of user {}", id),
+            UserList::Full(FullList::ListMembers(ref list)) => {
+                write!(f, "the members of list {}", list)
+            }
+            UserList::Full(FullList::ListSubscribers(ref list)) => {
+                write!(f, "the subscribers of list {}", list)
+            }
+            UserList::Ids(IdList::Retweeters(id)) => write!(f, "the retweeters of tweet {}", id),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "list" => Ok(Strategy::List),
+            "ids" => Ok(Strategy::Ids),
+            _ => Err(format!("unknown strategy: {}", s)),
+        }
+    }
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let strategy = opts.strategy;
+    let list = |id| match strategy {
+        Strategy::List => UserList::Full(FullList::Followers(id)),
+        Strategy::Ids => UserList::Ids(IdList::Followers(id)),
+    };
+    let lists = opts.users.into_iter().map(list).collect();
+    search(opts.search, lists).await
+}
+
+/// An account on whose behalf the lists are searched.
+struct Account {
+    id: i64,
+    client: twitter::Client,
+}
+
+/// Searches the lists for users who block you and blocks them.
+pub async fn search(opts: SearchOpts, lists: Vec<UserList>) -> Result<(), Error> {
+    let http = opts.api.http();
+    search_with(opts, lists, &http).await
+}
+
+/// Same as `search`, but sends the requests with `http`.
+async fn search_with(
+    opts: SearchOpts,
+    lists: Vec<UserList>,
+    http: &twitter::Http,
+) -> Result<(), Error> {
+    // The full user lists are retrieved with the tokens of the accounts, since their `blocked_by`
+    // is relative to the authenticated user.
+    if !opts.scan_with.is_empty() {
+        if let Some(list) = lists.iter().find(|l| matches!(l, UserList::Full(_))) {
+            return Err(Error::Input(format!(
+                "`--scan-with` is not supported for {}; use `--strategy ids` for followers",
+                list,
+            )));
+        }
+    }
 
-    let http = reqwest::Client::new();
+    let conn = connect_database(&opts.database)?;
+    let cipher = opts.key.cipher(&conn)?;
 
-    // Authenticated user
-    let auth = if let Some(id) = opts.login {
-        id
+    // Authenticated users
+    let auths = if opts.all_accounts {
+        let auths = query::accounts(&conn)?;
+        if auths.is_empty() {
+            return Err(Error::NoUser);
+        }
+        auths
+    } else if opts.login.is_empty() {
+        vec![query::authenticated_user(None, &conn)?]
     } else {
-        query::default_user(&conn).expect("`--login` option or default user is required")
+        let mut auths = opts.login.clone();
+        auths.sort_unstable();
+        auths.dedup();
+        auths
     };
 
-    let credentials = query::credentials(auth, &conn)
-        .unwrap_or_else(|| panic!("credentials not found for user: {}", auth));
-
-    let endpoint: i32 = if let Some(id) = endpoints::table
-        .select(endpoints::id)
-        .filter(endpoints::uri.eq(twitter::FOLLOWERS_LIST))
-        .get_result::<i32>(&conn)
-        .optional()
-        .unwrap()
-    {
-        id
+    let mut account_tokens = Vec::with_capacity(auths.len());
+    for auth in auths {
+        let tokens = query::credentials(auth, cipher.as_ref(), &conn)?;
+        if tokens.is_empty() {
+            return Err(Error::NoCredentials(auth));
+        }
+        log::debug!("Using {} tokens of user {}", tokens.len(), auth);
+        account_tokens.push((auth, tokens));
+    }
+
+    // Tokens to enumerate user IDs with: the ones of the helper accounts if any, or else the ones
+    // of all the authenticated users.
+    let scan_tokens = if opts.scan_with.is_empty() {
+        account_tokens
+            .iter()
+            .flat_map(|(_, tokens)| tokens.iter().cloned())
+            .collect()
     } else {
-        insert_into(endpoints::table)
-            .values(endpoints::uri.eq(twitter::FOLLOWERS_LIST))
-            .execute(&conn)
-            .unwrap();
-        endpoints::table
-            .select(endpoints::id)
-            .order(endpoints::id.desc())
-            .get_result::<i32>(&conn)
-            .unwrap()
+        let mut scan_tokens = Vec::new();
+        for &user in &opts.scan_with {
+            let tokens = query::credentials(user, cipher.as_ref(), &conn)?;
+            if tokens.is_empty() {
+                return Err(Error::NoCredentials(user));
+            }
+            scan_tokens.extend(tokens);
+        }
+        log::debug!(
+            "Using {} tokens of the helper accounts for enumeration",
+            scan_tokens.len()
+        );
+        scan_tokens
     };
+    let scanner = opts.blocker.client(scan_tokens, http);
+    query::load_rate_limits(scanner.limiter(), &conn)?;
 
-    let (tx, rx) = unbounded_channel();
+    // The accounts share the rate limits with `scanner`, which may use the same tokens.
+    let mut accounts = Vec::with_capacity(account_tokens.len());
+    for (auth, tokens) in account_tokens {
+        let client = opts.blocker.share(&scanner, tokens);
+        query::load_rate_limits(client.limiter(), &conn)?;
+        accounts.push(Account { id: auth, client });
+    }
 
-    // Receive user IDs from `searcher` and block them.
-    let blocker = blocker(auth, rx, &credentials, &conn, &http);
+    let action = opts.action();
 
-    let borrow = (&credentials, &conn, &http);
-    // Search for IDs of users who block `auth`, and send them to `blocker`.
+    // Receive user IDs from `searcher` and block (or mute) them on behalf of each account.
+    // If `action` is `None`, no blocker is started so that the actions left in `pending_actions`
+    // are not taken either, and `searcher` sends nothing to the closed channels.
+    let mut senders = Vec::with_capacity(accounts.len());
+    let mut blockers = Vec::with_capacity(accounts.len());
+    for account in &accounts {
+        let (tx, rx) = unbounded_channel();
+        senders.push(tx);
+        if let Some(action) = action {
+            let blocker = blocker(
+                action,
+                account.id,
+                rx,
+                &opts.blocker,
+                &account.client,
+                &conn,
+            );
+            let auth = account.id;
+            blockers.push(blocker.map(move |result| result.map_err(|e| e.for_account(auth))));
+        }
+    }
+    let blockers = futures::future::try_join_all(blockers);
+
+    let targets: Vec<_> = accounts.iter().zip(senders).collect();
+    let borrow = (&opts, &scanner, &conn);
+    // Search for IDs of users who block the accounts, and send them to `blockers`.
     let searcher = async move {
-        let (credentials, conn, http) = borrow;
+        let (opts, scanner, conn) = borrow;
 
-        'outer: for &user in &opts.users {
-            let mut cursor = if opts.reset {
+        'outer: for list in &lists {
+            let endpoint = query::endpoint(list.endpoint(), conn)?;
+            let user = if let Some(id) = list.id() {
+                id
+            } else if let Some(id) = show_list(list, scanner).await? {
+                id
+            } else {
+                continue;
+            };
+            query::save_rate_limits(scanner.limiter(), conn)?;
+            let save_cursor = |auth: i64, cursor: i64| {
                 replace_into(user_list_cursors::table)
                     .values((
                         user_list_cursors::endpoint.eq(endpoint),
                         user_list_cursors::authenticated_user.eq(auth),
                         user_list_cursors::user.eq(user),
-                        user_list_cursors::cursor.eq(-1),
+                        user_list_cursors::cursor.eq(cursor),
                     ))
                     .execute(conn)
-                    .unwrap();
-                -1
-            } else {
-                user_list_cursors::table
-                    .select(user_list_cursors::cursor)
-                    .find((endpoint, auth, user))
-                    .get_result::<i64>(conn)
-                    .optional()
-                    .unwrap()
-                    .unwrap_or(-1)
+            };
+            let queue_lookups = |auth: i64, ids: &[i64]| {
+                let inserts: Vec<_> = ids
+                    .iter()
+                    .map(|&id| {
+                        (
+                            pending_lookups::endpoint.eq(endpoint),
+                            pending_lookups::authenticated_user.eq(auth),
+                            pending_lookups::user.eq(user),
+                            pending_lookups::target.eq(id),
+                        )
+                    })
+                    .collect();
+                insert_or_ignore_into(pending_lookups::table)
+                    .values(inserts)
+                    .execute(conn)
             };
 
-            log::info!("Started searching the followers of user {}", user);
-
-            while cursor != 0 {
-                let oauth::Request {
-                    authorization,
-                    data: uri,
-                } = oauth::Builder::new(credentials.client(), oauth::HmacSha1)
-                    .token(credentials.token())
-                    .get(
-                        twitter::FOLLOWERS_LIST,
-                        &twitter::FollowersList {
-                            user_id: user,
-                            count: 200,
-                            skip_status: true,
-                            include_user_entities: false,
-                            cursor,
-                        },
-                    );
+            let mut cursors = Vec::with_capacity(targets.len());
+            for &(account, _) in &targets {
+                let cursor = if opts.reset {
+                    conn.transaction::<_, Error, _>(|| {
+                        delete(pending_lookups(endpoint, account.id, user)).execute(conn)?;
+                        save_cursor(account.id, -1)?;
+                        Ok(())
+                    })?;
+                    -1
+                } else {
+                    user_list_cursors::table
+                        .select(user_list_cursors::cursor)
+                        .find((endpoint, account.id, user))
+                        .get_result::<i64>(conn)
+                        .optional()?
+                        .unwrap_or(-1)
+                };
+                cursors.push(cursor);
+            }
 
-                log::info!("Retrieving the follower list with cursor = {}", cursor);
-                let response = http
-                    .get(&uri)
-                    .header(AUTHORIZATION, authorization)
-                    .send()
-                    .await
-                    .unwrap();
-                let rate_limit = twitter::rate_limit(response.headers());
-
-                match response.status() {
-                    StatusCode::TOO_MANY_REQUESTS => {
-                        log::warn!("Got a TooManyRequest error");
-                        wait_until(rate_limit.unwrap().reset + 1).await;
-                        continue;
-                    }
-                    StatusCode::NOT_FOUND => {
-                        log::error!("The user was not found");
-                        continue 'outer;
-                    }
-                    s if s.is_success() => {}
-                    s => {
-                        log::error!("Unexpected status code: {:?}", s);
-                        log::error!("Response body: {:?}", response.text().await);
-                        return;
-                    }
-                }
+            log::info!("Started searching {}", list);
 
-                let users: twitter::Users = response.json().await.unwrap();
-
-                let blockers: Vec<_> = users.users.iter().filter(|u| u.blocked_by).collect();
-                if !blockers.is_empty() {
-                    let user_inserts: Vec<_> =
-                        blockers.iter().map(|u| users::id.eq(u.id)).collect();
-                    insert_or_ignore_into(users::table)
-                        .values(user_inserts)
-                        .execute(conn)
-                        .unwrap();
-                    let blocks: Vec<_> = blockers
-                        .iter()
-                        .map(|u| (blocks::source.eq(u.id), blocks::target.eq(auth)))
-                        .collect();
-                    insert_or_ignore_into(blocks::table)
-                        .values(blocks)
-                        .execute(conn)
-                        .unwrap();
-                }
-                for u in &blockers {
-                    if u.blocking {
-                        log::info!("User {} and {} block each other", u.id, auth);
-                    } else {
-                        log::info!("User {} has blocked {}", u.id, auth);
-                        if !opts.no_block {
-                            tx.send(u.id)
-                                .expect("receiver half has been closed unexpectedly");
+            // The accounts at the same cursor share the enumeration of the list.
+            let mut groups: BTreeMap<i64, Vec<_>> = BTreeMap::new();
+            for (target, cursor) in targets.iter().zip(cursors) {
+                groups.entry(cursor).or_default().push(target);
+            }
+
+            match *list {
+                UserList::Ids(ref ids_list) => {
+                    for (mut cursor, group) in groups {
+                        loop {
+                            // Look up the users whose IDs have been retrieved on behalf of each
+                            // account, including the ones left by an interrupted run.
+                            let lookups = group.iter().map(|&(account, tx)| {
+                                lookup_pending(action, account, tx, endpoint, user, conn)
+                            });
+                            futures::future::try_join_all(lookups).await?;
+
+                            if cursor == 0 {
+                                break;
+                            }
+
+                            log::info!("Retrieving the user ID list with cursor = {}", cursor);
+                            let ids = match ids_list.fetch(cursor, scanner).await? {
+                                Fetch::Ok(ids) => ids,
+                                Fetch::Unavailable(e) => {
+                                    log::error!("Unable to retrieve {}: {}", list, e);
+                                    continue 'outer;
+                                }
+                            };
+                            query::save_rate_limits(scanner.limiter(), conn)?;
+
+                            cursor = ids.next_cursor;
+                            conn.transaction::<_, Error, _>(|| {
+                                for &(account, _) in &group {
+                                    queue_lookups(account.id, &ids.ids)?;
+                                    save_cursor(account.id, cursor)?;
+                                }
+                                Ok(())
+                            })?;
                         }
                     }
                 }
+                UserList::Full(ref full_list) => {
+                    // `blocked_by` in the user list is relative to the account retrieving it, so
+                    // the other accounts in the group look up the retrieved users by themselves.
+                    for (mut cursor, mut group) in groups {
+                        loop {
+                            let lookups = group.iter().map(|&(account, tx)| {
+                                lookup_pending(action, account, tx, endpoint, user, conn)
+                            });
+                            futures::future::try_join_all(lookups).await?;
 
-                cursor = users.next_cursor;
-                replace_into(user_list_cursors::table)
-                    .values((
-                        user_list_cursors::endpoint.eq(endpoint),
-                        user_list_cursors::authenticated_user.eq(auth),
-                        user_list_cursors::user.eq(user),
-                        user_list_cursors::cursor.eq(cursor),
-                    ))
-                    .execute(conn)
-                    .unwrap();
+                            if cursor == 0 {
+                                break;
+                            }
+                            let (lead, lead_tx) = match group.first() {
+                                Some(&&(account, ref tx)) => (account, tx),
+                                None => break,
+                            };
+
+                            log::info!(
+                                "Retrieving the user list with cursor = {} on behalf of {}",
+                                cursor,
+                                lead.id
+                            );
+                            let users = match full_list.fetch(cursor, &lead.client).await? {
+                                Fetch::Ok(users) => users,
+                                Fetch::Unavailable(e) => {
+                                    log::error!(
+                                        "Unable to retrieve {} on behalf of {}: {}",
+                                        list,
+                                        lead.id,
+                                        e
+                                    );
+                                    // Let the next account in the group retrieve the list.
+                                    group.remove(0);
+                                    continue;
+                                }
+                            };
+                            query::save_rate_limits(lead.client.limiter(), conn)?;
 
-                if let Some(rl) = rate_limit {
-                    if rl.remaining == 0 {
-                        log::info!("Rate limit exhausted");
-                        wait_until(rl.reset + 1).await;
+                            cursor = users.next_cursor;
+                            let ids: Vec<i64> = users.users.iter().map(|u| u.id).collect();
+                            let blockers = conn.transaction::<_, Error, _>(|| {
+                                let blockers = handle_users(action, lead.id, &users.users, conn)?;
+                                save_cursor(lead.id, cursor)?;
+                                for &(account, _) in &group[1..] {
+                                    queue_lookups(account.id, &ids)?;
+                                    save_cursor(account.id, cursor)?;
+                                }
+                                Ok(blockers)
+                            })?;
+                            send(lead_tx, blockers);
+                        }
                     }
                 }
             }
 
-            log::info!("Finished searching the followers of user {}", user);
+            log::info!("Finished searching {}", list);
         }
+
+        for &(account, _) in &targets {
+            query::save_rate_limits(account.client.limiter(), conn)?;
+        }
+        query::save_rate_limits(scanner.limiter(), conn)?;
+
+        Ok(())
     };
 
-    futures::future::join(searcher, blocker).await;
+    // Poll `blockers` first so that they load the actions left in `pending_actions` before
+    // `searcher` queues new ones, which would otherwise be taken twice.
+    futures::future::try_join(blockers, searcher).await?;
+
+    Ok(())
 }
 
-fn blocker<'a>(
+/// Returns the `pending_lookups` rows of the list of `user` retrieved from `endpoint` on behalf
+/// of `auth`.
+fn pending_lookups(endpoint: i32, auth: i64, user: i64) -> PendingLookups {
+    pending_lookups::table
+        .filter(pending_lookups::endpoint.eq(endpoint))
+        .filter(pending_lookups::authenticated_user.eq(auth))
+        .filter(pending_lookups::user.eq(user))
+}
+
+type PendingLookups = Filter<
+    Filter<
+        Filter<pending_lookups::table, diesel::dsl::Eq<pending_lookups::endpoint, i32>>,
+        diesel::dsl::Eq<pending_lookups::authenticated_user, i64>,
+    >,
+    diesel::dsl::Eq<pending_lookups::user, i64>,
+>;
+
+/// Looks up the users left in `pending_lookups` on behalf of `account`, and sends the ones who
+/// block the account to `tx`.
+async fn lookup_pending(
+    action: Option<Action>,
+    account: &Account,
+    tx: &UnboundedSender<i64>,
+    endpoint: i32,
+    user: i64,
+    conn: &SqliteConnection,
+) -> Result<(), Error> {
+    let pending = pending_lookups(endpoint, account.id, user);
+    loop {
+        let ids: Vec<i64> = pending
+            .select(pending_lookups::target)
+            .limit(100)
+            .load(conn)?;
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        log::info!("Looking up {} users on behalf of {}", ids.len(), account.id);
+        let users = match account.client.users_lookup(&ids).await? {
+            Fetch::Ok(users) => users,
+            // None of the users are available anymore.
+            Fetch::Unavailable(_) => Vec::new(),
+        };
+        query::save_rate_limits(account.client.limiter(), conn)?;
+
+        let blockers = conn.transaction::<_, Error, _>(|| {
+            let blockers = handle_users(action, account.id, &users, conn)?;
+            delete(pending.filter(pending_lookups::target.eq_any(&ids))).execute(conn)?;
+            Ok(blockers)
+        })?;
+        send(tx, blockers);
+    }
+}
+
+/// Records the profiles of `users` and the users who block `auth`, and queues `action` on the
+/// latter in `pending_actions`.
+///
+/// Returns the IDs of the queued users, which should be sent to `blocker` (see `send`) after the
+/// transaction is committed.
+fn handle_users(
+    action: Option<Action>,
     auth: i64,
-    mut rx: impl Stream<Item = i64> + Unpin + 'a,
-    credentials: &'a crate::auth::Token,
-    conn: &'a SqliteConnection,
-    http: &'a reqwest::Client,
-) -> impl Future<Output = ()> + 'a {
+    users: &[twitter::User],
+    conn: &SqliteConnection,
+) -> QueryResult<Vec<i64>> {
+    // This also inserts the `users` rows referenced by `blocks` below.
+    query::save_profiles(users, conn)?;
+
+    let blockers: Vec<_> = users.iter().filter(|u| u.blocked_by).collect();
+    if !blockers.is_empty() {
+        let blocks: Vec<_> = blockers
+            .iter()
+            .map(|u| (blocks::source.eq(u.id), blocks::target.eq(auth)))
+            .collect();
+        insert_or_ignore_into(blocks::table)
+            .values(blocks)
+            .execute(conn)?;
+    }
+
+    let mut targets = Vec::new();
+    for u in &blockers {
+        if u.blocking {
+            log::info!("User {} and {} block each other", u.id, auth);
+        } else {
+            log::info!("User {} has blocked {}", u.id, auth);
+            match action {
+                Some(Action::Mute) if u.muting => {
+                    log::info!("User {} has already been muted", u.id);
+                }
+                Some(_) => targets.push(u.id),
+                None => {}
+            }
+        }
+    }
+
+    if let Some(action) = action {
+        query::queue_actions(action, auth, &targets, conn)?;
+    }
+
+    Ok(targets)
+}
+
+/// Sends the users returned by `handle_users` to `blocker`.
+fn send(tx: &UnboundedSender<i64>, targets: Vec<i64>) {
+    for id in targets {
+        tx.send(id)
+            .expect("receiver half has been closed unexpectedly");
+    }
+}
+
+/// Retrieves the ID of the Twitter List referred to by its slug.
+async fn show_list(list: &UserList, client: &twitter::Client) -> Result<Option<i64>, Error> {
+    let list_ref = match *list {
+        UserList::Full(FullList::ListMembers(ref list))
+        | UserList::Full(FullList::ListSubscribers(ref list)) => list,
+        _ => return Ok(list.id()),
+    };
+
+    log::info!("Retrieving the ID of {}", list);
+    match client.lists_show(list_ref).await? {
+        Fetch::Ok(list) => Ok(Some(list.id)),
+        Fetch::Unavailable(e) => {
+            log::error!("Unable to retrieve {}: {}", list, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Returns a future that takes `action` on the users received from `rx` on behalf of `auth`.
+///
+/// The users must have been queued in `pending_actions` (see `query::queue_actions`) before being
+/// sent to `rx`. The future also takes `action` on the users left in `pending_actions` by
+/// interrupted runs, and removes the users from `pending_actions` once the action succeeds.
+///
+/// Failed actions are retried with exponential backoff, and moved to `failed_actions` after
+/// `opts.max_attempts` attempts. Actions on users who are not found or who block `auth` are
+/// moved to `failed_actions` without retrying.
+pub async fn blocker(
+    action: Action,
+    auth: i64,
+    mut rx: impl Stream<Item = i64> + Unpin,
+    opts: &BlockerOpts,
+    client: &twitter::Client,
+    conn: &SqliteConnection,
+) -> Result<(), Error> {
+    let failed = failed_actions::table
+        .filter(failed_actions::authenticated_user.eq(auth))
+        .filter(failed_actions::action.eq(action.as_str()));
+    if opts.retry_failed {
+        conn.transaction::<_, Error, _>(|| {
+            let targets = failed.select(failed_actions::target).load::<i64>(conn)?;
+            log::info!(
+                "Retrying {} failed {} actions",
+                targets.len(),
+                action.as_str()
+            );
+            query::queue_actions(action, auth, &targets, conn)?;
+            delete(failed).execute(conn)?;
+            Ok(())
+        })?;
+    }
+
     // User IDs to block
-    let mut block_queue = VecDeque::new();
+    let mut block_queue: VecDeque<i64> = pending_actions::table
+        .select(pending_actions::target)
+        .filter(pending_actions::authenticated_user.eq(auth))
+        .filter(pending_actions::action.eq(action.as_str()))
+        .load::<i64>(conn)?
+        .into();
+    if !block_queue.is_empty() {
+        log::info!(
+            "Resuming {} pending {} actions",
+            block_queue.len(),
+            action.as_str()
+        );
+    }
     // Stores `impl Future<Output = (http_response_future, user_id_to_block)>`
     let mut blocking = FuturesUnordered::new();
+    // Stores `impl Future<Output = user_id_to_retry>` that completes after the backoff delay
+    let mut backoff = FuturesUnordered::new();
+    // Number of failed attempts for each user
+    let mut attempts: HashMap<i64, u32> = HashMap::new();
     // Timer to wait for the rate limit
     let mut timer = tokio::time::delay_until(tokio::time::Instant::now());
 
+    // Moves the action on `id` from `pending_actions` to `failed_actions`.
+    let give_up =
+        |id: i64, attempts: u32, status: Option<StatusCode>, code: Option<u32>, error: &str| {
+            conn.transaction::<_, Error, _>(|| {
+                replace_into(failed_actions::table)
+                    .values((
+                        failed_actions::authenticated_user.eq(auth),
+                        failed_actions::target.eq(id),
+                        failed_actions::action.eq(action.as_str()),
+                        failed_actions::attempts.eq(attempts as i32),
+                        failed_actions::status.eq(status.map(|s| i32::from(s.as_u16()))),
+                        failed_actions::code.eq(code.map(|c| c as i32)),
+                        failed_actions::error.eq(error),
+                    ))
+                    .execute(conn)?;
+                delete(pending_actions::table.find((auth, id, action.as_str()))).execute(conn)?;
+                Ok(())
+            })
+        };
+
     let future = futures::future::poll_fn(move |cx| {
         log::trace!("Polled the blocker future");
 
-        let rx_done = loop {
-            match rx.poll_next_unpin(cx) {
-                Poll::Ready(Some(id)) => block_queue.push_back(id),
-                Poll::Ready(None) => break true,
-                Poll::Pending => break false,
-            }
-        };
-
-        while let Poll::Ready(Some((result, id))) = blocking.poll_next_unpin(cx) {
-            let response: reqwest::Response = match result {
-                Ok(response) => response,
-                Err(e) => {
-                    log::error!("HTTP client error: {:?}", e);
-                    block_queue.push_front(id);
-                    continue;
+        loop {
+            let rx_done = loop {
+                match rx.poll_next_unpin(cx) {
+                    Poll::Ready(Some(id)) => block_queue.push_back(id),
+                    Poll::Ready(None) => break true,
+                    Poll::Pending => break false,
                 }
             };
-            match response.status() {
-                s if s.is_success() => {}
-                StatusCode::NOT_FOUND => continue,
-                StatusCode::TOO_MANY_REQUESTS => {
-                    log::warn!("Got a TooManyRequest error");
-                    block_queue.push_front(id);
-                    let reset = twitter::rate_limit(response.headers()).unwrap().reset;
-                    if reset > instant_to_epoch(timer.deadline()) {
-                        timer = wait_until(reset + 1);
+
+            while let Poll::Ready(Some(id)) = backoff.poll_next_unpin(cx) {
+                block_queue.push_back(id);
+            }
+
+            // Whether new futures have been pushed, which have to be polled to register wakers.
+            let mut pushed = false;
+
+            if let Poll::Ready(()) = timer.poll_unpin(cx) {
+                while let Some(&id) = block_queue.front() {
+                    match client.limiter().try_acquire(action.endpoint(), cx.waker()) {
+                        twitter::Acquire::Ready(permit) => {
+                            block_queue.pop_front();
+                            pushed = true;
+                            blocking.push(
+                                action
+                                    .send(client, permit, id)
+                                    .map(move |result| (result, id)),
+                            );
+                        }
+                        twitter::Acquire::Wait(reset) => {
+                            log::info!("Rate limit of {} exhausted", action.endpoint());
+                            timer = wait_until(reset + 1);
+                            pushed = true;
+                            break;
+                        }
+                        twitter::Acquire::Busy => break,
                     }
-                    continue;
-                }
-                s => {
-                    log::error!("Unexpected status code: {:?}", s);
-                    // TODO: limit the number of retrials
-                    block_queue.push_front(id);
-                    continue;
                 }
             }
 
-            insert_or_ignore_into(blocks::table)
-                .values((blocks::source.eq(auth), blocks::target.eq(id)))
-                .execute(conn)
-                .unwrap();
-        }
+            while let Poll::Ready(Some((result, id))) = blocking.poll_next_unpin(cx) {
+                let (status, code, error) = match result {
+                    Ok(()) => {
+                        attempts.remove(&id);
+                        let result = conn.transaction::<_, Error, _>(|| {
+                            action.record(auth, id, conn)?;
+                            delete(pending_actions::table.find((auth, id, action.as_str())))
+                                .execute(conn)?;
+                            Ok(())
+                        });
+                        if let Err(e) = result {
+                            return Poll::Ready(Err(e));
+                        }
+                        continue;
+                    }
+                    Err(Error::Api(e)) => match e.kind() {
+                        ErrorKind::NotFound | ErrorKind::Blocked => {
+                            log::warn!("Skipping user {}: {}", id, e);
+                            let attempts = attempts.remove(&id).unwrap_or(0) + 1;
+                            let code = e.errors.first().map(|e| e.code);
+                            let error = e.to_string();
+                            if let Err(e) = give_up(id, attempts, Some(e.status), code, &error) {
+                                return Poll::Ready(Err(e));
+                            }
+                            continue;
+                        }
+                        ErrorKind::RateLimited => {
+                            log::warn!("Got a rate limit error");
+                            block_queue.push_front(id);
+                            // `limiter` has recorded the exhausted rate limit of the token and
+                            // lets the other tokens take over. Without the rate limit headers,
+                            // wait for a whole rate limit window.
+                            if e.rate_limit.is_none() {
+                                timer = tokio::time::delay_for(Duration::from_secs(15 * 60));
+                            }
+                            continue;
+                        }
+                        ErrorKind::InvalidToken | ErrorKind::AccountLocked => {
+                            return Poll::Ready(Err(Error::Api(e)));
+                        }
+                        ErrorKind::NotAuthorized | ErrorKind::Other => {
+                            log::error!("Unexpected error: {}", e);
+                            (
+                                Some(e.status),
+                                e.errors.first().map(|e| e.code),
+                                e.to_string(),
+                            )
+                        }
+                    },
+                    Err(e) => {
+                        log::error!("HTTP client error: {:?}", e);
+                        let status = match e {
+                            Error::Http(ref e) => e.status(),
+                            _ => None,
+                        };
+                        (status, None, e.to_string())
+                    }
+                };
+
+                let n = attempts.entry(id).or_insert(0);
+                *n += 1;
+                if *n < opts.max_attempts {
+                    let delay = backoff_delay(*n);
+                    log::info!(
+                        "Retrying {} user {} in {} secs",
+                        action.as_str(),
+                        id,
+                        delay.as_secs()
+                    );
+                    pushed = true;
+                    backoff.push(tokio::time::delay_for(delay).map(move |()| id));
+                } else {
+                    log::error!(
+                        "Gave up taking {} action on user {} after {} attempts",
+                        action.as_str(),
+                        id,
+                        n
+                    );
+                    let attempts = attempts.remove(&id).unwrap();
+                    if let Err(e) = give_up(id, attempts, status, code, &error) {
+                        return Poll::Ready(Err(e));
+                    }
+                }
+            }
 
-        if let Poll::Ready(()) = timer.poll_unpin(cx) {
-            // TODO: limit the number of requests based on `rate-limit-remaining`
-            for id in block_queue.drain(..) {
-                blocking.push(block(id, credentials, &http).map(move |response| (response, id)));
+            if let Err(e) = query::save_rate_limits(client.limiter(), conn) {
+                return Poll::Ready(Err(e.into()));
             }
-        }
 
-        if rx_done && block_queue.is_empty() && blocking.is_empty() {
-            Poll::Ready(())
-        } else {
-            Poll::Pending
+            if !pushed {
+                return if rx_done
+                    && block_queue.is_empty()
+                    && blocking.is_empty()
+                    && backoff.is_empty()
+                {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                };
+            }
         }
     });
 
-    future
+    future.await
 }
 
-fn block(
-    user: i64,
-    credentials: &crate::auth::Token,
-    http: &reqwest::Client,
-) -> impl Future<Output = Result<reqwest::Response, reqwest::Error>> {
-    let oauth::Request {
-        authorization,
-        data: uri,
-    } = oauth::Builder::new(credentials.client(), oauth::HmacSha1)
-        .token(credentials.token())
-        .get(
-            twitter::BLOCKS_CREATE,
-            twitter::BlocksCreate {
-                user_id: user,
-                include_entities: false,
-                skip_status: true,
-            },
+/// Returns the delay before the `n`-th retry, which grows exponentially from 2 secs up to
+/// 15 minutes, with a random jitter of up to a half of the delay.
+fn backoff_delay(n: u32) -> Duration {
+    const BASE: u64 = 2;
+    const MAX: u64 = 15 * 60;
+
+    let delay = BASE.saturating_mul(1 << (n - 1).min(16)).min(MAX) * 1000;
+    let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+    Duration::from_millis(delay - jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Database;
+
+    fn search_opts(db: &Database, args: &[&str]) -> SearchOpts {
+        let head = ["test", "--database", db.path()];
+        SearchOpts::from_iter(head.iter().chain(args))
+    }
+
+    fn user(id: i64, blocked_by: bool) -> String {
+        format!(
+            r#"{{"id":{0},"screen_name":"user{0}","name":"User {0}","description":"",
+                "location":"","url":null,"created_at":"Wed Oct 10 20:19:24 +0000 2018",
+                "followers_count":1,"friends_count":2,"statuses_count":3,"protected":false,
+                "verified":false,"default_profile_image":true,"blocking":false,
+                "blocked_by":{1}}}"#,
+            id, blocked_by,
+        )
+    }
+
+    fn blocks(conn: &SqliteConnection) -> Vec<(i64, i64)> {
+        blocks::table
+            .select((blocks::source, blocks::target))
+            .order((blocks::source, blocks::target))
+            .load(conn)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn search_user_list() {
+        let db = Database::new("search_user_list");
+        db.authorize(1, 1);
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let page = |users: &[String], next_cursor: i64| {
+            format!(
+                r#"{{"users":[{}],"next_cursor":{},"previous_cursor":0}}"#,
+                users.join(","),
+                next_cursor,
+            )
+        };
+        let first = page(&[user(2, true), user(3, false)], 42);
+        let second = page(&[user(4, true)], 0);
+        fake.respond_once(twitter::FOLLOWERS_LIST, 200, &first, None);
+        fake.respond_once(twitter::FOLLOWERS_LIST, 200, &second, None);
+        fake.respond(twitter::BLOCKS_CREATE, 200, "{}");
+
+        let opts = search_opts(&db, &["--login", "1"]);
+        search_with(opts, vec![UserList::Full(FullList::Followers(10))], &http)
+            .await
+            .unwrap();
+
+        assert_eq!(fake.count(twitter::FOLLOWERS_LIST), 2);
+        let second = fake
+            .requests()
+            .into_iter()
+            .filter(|uri| uri.starts_with(twitter::FOLLOWERS_LIST))
+            .nth(1)
+            .unwrap();
+        assert!(second.contains("cursor=42"));
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 2);
+        assert_eq!(blocks(&db.conn), [(1, 2), (1, 4), (2, 1), (4, 1)]);
+        let cursor = user_list_cursors::table
+            .select(user_list_cursors::cursor)
+            .get_result::<i64>(&db.conn)
+            .unwrap();
+        assert_eq!(cursor, 0);
+        let pending = pending_actions::table.count().get_result::<i64>(&db.conn);
+        assert_eq!(pending.unwrap(), 0);
+
+        // The profiles of all the users in the list are recorded.
+        let profiles = user_profiles::table
+            .select((
+                user_profiles::user,
+                user_profiles::screen_name,
+                user_profiles::created_at,
+            ))
+            .order(user_profiles::user)
+            .load::<(i64, String, i64)>(&db.conn)
+            .unwrap();
+        let created_at = 1539202764;
+        assert_eq!(
+            profiles,
+            [
+                (2, "user2".to_owned(), created_at),
+                (3, "user3".to_owned(), created_at),
+                (4, "user4".to_owned(), created_at),
+            ],
         );
-    http.get(&uri).header(AUTHORIZATION, authorization).send()
+    }
+
+    #[tokio::test]
+    async fn search_ids_shared_by_accounts() {
+        let db = Database::new("search_ids_shared_by_accounts");
+        db.authorize(1, 1);
+        db.authorize(2, 1);
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let ids = r#"{"ids":[3,4],"next_cursor":0,"previous_cursor":0}"#;
+        fake.respond_once(twitter::FOLLOWERS_IDS, 200, ids, None);
+        let users = format!("[{},{}]", user(3, true), user(4, false));
+        fake.respond(twitter::USERS_LOOKUP, 200, &users);
+        fake.respond(twitter::BLOCKS_CREATE, 200, "{}");
+
+        let opts = search_opts(&db, &["--all-accounts"]);
+        search_with(opts, vec![UserList::Ids(IdList::Followers(10))], &http)
+            .await
+            .unwrap();
+
+        // The IDs are retrieved once and looked up on behalf of each account.
+        assert_eq!(fake.count(twitter::FOLLOWERS_IDS), 1);
+        assert_eq!(fake.count(twitter::USERS_LOOKUP), 2);
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 2);
+        assert_eq!(blocks(&db.conn), [(1, 3), (2, 3), (3, 1), (3, 2)]);
+        let pending = pending_lookups::table.count().get_result::<i64>(&db.conn);
+        assert_eq!(pending.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn search_user_list_shared_by_accounts() {
+        let db = Database::new("search_user_list_shared_by_accounts");
+        db.authorize(1, 1);
+        db.authorize(2, 1);
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let page = format!(
+            r#"{{"users":[{},{}],"next_cursor":0,"previous_cursor":0}}"#,
+            user(3, true),
+            user(4, false),
+        );
+        fake.respond_once(twitter::FOLLOWERS_LIST, 200, &page, None);
+        let users = format!("[{},{}]", user(3, false), user(4, true));
+        fake.respond(twitter::USERS_LOOKUP, 200, &users);
+        fake.respond(twitter::BLOCKS_CREATE, 200, "{}");
+
+        let opts = search_opts(&db, &["--all-accounts"]);
+        search_with(opts, vec![UserList::Full(FullList::Followers(10))], &http)
+            .await
+            .unwrap();
+
+        // The list is retrieved once on behalf of the first account, and the users are looked up
+        // on behalf of the other one.
+        assert_eq!(fake.count(twitter::FOLLOWERS_LIST), 1);
+        assert_eq!(fake.count(twitter::USERS_LOOKUP), 1);
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 2);
+        assert_eq!(blocks(&db.conn), [(1, 3), (2, 4), (3, 1), (4, 2)]);
+        let cursors = user_list_cursors::table
+            .select(user_list_cursors::cursor)
+            .load::<i64>(&db.conn)
+            .unwrap();
+        assert_eq!(cursors, [0, 0]);
+        let pending = pending_lookups::table.count().get_result::<i64>(&db.conn);
+        assert_eq!(pending.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn search_skips_protected_list() {
+        let db = Database::new("search_skips_protected_list");
+        db.authorize(1, 1);
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let not_authorized = r#"{"request":"/1.1/followers/ids.json","error":"Not authorized."}"#;
+        fake.respond(twitter::FOLLOWERS_IDS, 401, not_authorized);
+
+        // The list is skipped instead of treating the credentials as unusable.
+        let opts = search_opts(&db, &["--login", "1"]);
+        search_with(opts, vec![UserList::Ids(IdList::Followers(10))], &http)
+            .await
+            .unwrap();
+        assert_eq!(fake.count(twitter::FOLLOWERS_IDS), 1);
+        assert!(blocks(&db.conn).is_empty());
+    }
+
+    #[tokio::test]
+    async fn scan_with_full_list() {
+        let db = Database::new("scan_with_full_list");
+        db.authorize(1, 1);
+        db.authorize(2, 1);
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+
+        let opts = search_opts(&db, &["--login", "1", "--scan-with", "2"]);
+        let result = search_with(opts, vec![UserList::Full(FullList::Friends(10))], &http).await;
+        assert!(matches!(result, Err(Error::Input(_))));
+        assert!(fake.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_without_action() {
+        let db = Database::new("search_without_action");
+        db.authorize(1, 1);
+        let conn = &db.conn;
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let page = format!(
+            r#"{{"users":[{}],"next_cursor":0,"previous_cursor":0}}"#,
+            user(3, true),
+        );
+        fake.respond(twitter::FOLLOWERS_LIST, 200, &page);
+        fake.respond(twitter::BLOCKS_CREATE, 200, "{}");
+
+        // A block action left by an interrupted run
+        insert_into(users::table)
+            .values(users::id.eq(2))
+            .execute(conn)
+            .unwrap();
+        query::queue_actions(Action::Block, 1, &[2], conn).unwrap();
+
+        let opts = search_opts(&db, &["--login", "1", "--no-block"]);
+        search_with(opts, vec![UserList::Full(FullList::Followers(10))], &http)
+            .await
+            .unwrap();
+
+        // The blocks are recorded, but neither the found user nor the pending one is blocked.
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 0);
+        assert_eq!(blocks(conn), [(3, 1)]);
+        let pending = pending_actions::table
+            .select(pending_actions::target)
+            .load::<i64>(conn)
+            .unwrap();
+        assert_eq!(pending, [2]);
+    }
+
+    #[tokio::test]
+    async fn blocker_skips_unavailable_users() {
+        let db = Database::new("blocker_skips_unavailable_users");
+        let tokens = db.authorize(1, 1);
+        let conn = &db.conn;
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let not_found = r#"{"errors":[{"code":50,"message":"User not found."}]}"#;
+        fake.respond(twitter::BLOCKS_CREATE, 404, not_found);
+
+        insert_into(users::table)
+            .values(users::id.eq(2))
+            .execute(conn)
+            .unwrap();
+        query::queue_actions(Action::Block, 1, &[2], conn).unwrap();
+
+        let opts = BlockerOpts::from_iter(&["test", "--max-attempts", "3"]);
+        let client = opts.client(tokens, &http);
+        let rx = futures::stream::empty();
+        blocker(Action::Block, 1, rx, &opts, &client, conn)
+            .await
+            .unwrap();
+
+        // The user is skipped without retrying, and the error is recorded.
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 1);
+        assert!(blocks(conn).is_empty());
+        let failed = failed_actions::table
+            .select((
+                failed_actions::target,
+                failed_actions::attempts,
+                failed_actions::status,
+                failed_actions::code,
+                failed_actions::error,
+            ))
+            .load::<(i64, i32, Option<i32>, Option<i32>, String)>(conn)
+            .unwrap();
+        assert_eq!(
+            failed,
+            [(
+                2,
+                1,
+                Some(404),
+                Some(50),
+                "404 Not Found: User not found. (code 50)".to_owned(),
+            )],
+        );
+        let pending = pending_actions::table.count().get_result::<i64>(conn);
+        assert_eq!(pending.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn blocker_retries_and_gives_up() {
+        let db = Database::new("blocker_retries_and_gives_up");
+        let tokens = db.authorize(1, 2);
+        let conn = &db.conn;
+
+        let fake = twitter::Fake::default();
+        let http = twitter::Http::new(fake.clone(), twitter::API_BASE);
+        let reset = crate::common::now() + 15 * 60;
+        let rate_limited = r#"{"errors":[{"code":88,"message":"Rate limit exceeded"}]}"#;
+        fake.respond_once(twitter::BLOCKS_CREATE, 429, rate_limited, Some((0, reset)));
+        fake.respond_once(twitter::BLOCKS_CREATE, 500, "Internal Server Error", None);
+        fake.respond(twitter::BLOCKS_CREATE, 200, "{}");
+
+        insert_into(users::table)
+            .values(&vec![users::id.eq(2), users::id.eq(3)])
+            .execute(conn)
+            .unwrap();
+        query::queue_actions(Action::Block, 1, &[2, 3], conn).unwrap();
+
+        let opts = BlockerOpts::from_iter(&["test", "--max-attempts", "1"]);
+        let client = opts.client(tokens.clone(), &http);
+        let rx = futures::stream::empty();
+        blocker(Action::Block, 1, rx, &opts, &client, conn)
+            .await
+            .unwrap();
+
+        // User 2 is blocked with the other token after the rate limit error, while user 3 is
+        // given up after the server error.
+        assert_eq!(fake.count(twitter::BLOCKS_CREATE), 3);
+        assert_eq!(blocks(conn), [(1, 2)]);
+        let failed = failed_actions::table
+            .select((failed_actions::target, failed_actions::status))
+            .load::<(i64, Option<i32>)>(conn)
+            .unwrap();
+        assert_eq!(failed, [(3, Some(500))]);
+        let pending = pending_actions::table.count().get_result::<i64>(conn);
+        assert_eq!(pending.unwrap(), 0);
+
+        // The exhausted rate limit is persisted for the next run.
+        let rate_limits = rate_limits::table
+            .select((rate_limits::token, rate_limits::remaining))
+            .load::<(i32, i64)>(conn)
+            .unwrap();
+        assert_eq!(rate_limits, [(tokens[0].id, 0)]);
+    }
 }
@@ -0,0 +1,22 @@
+use structopt::StructOpt;
+
+use crate::cmd::followers::{self, FullList, SearchOpts, UserList};
+use crate::error::Error;
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// User IDs of the users to search friends of
+    users: Vec<i64>,
+    #[structopt(flatten)]
+    search: SearchOpts,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let lists = opts
+        .users
+        .into_iter()
+        .map(FullList::Friends)
+        .map(UserList::Full)
+        .collect();
+    followers::search(opts.search, lists).await
+}
@@ -0,0 +1,23 @@
+use structopt::StructOpt;
+
+use crate::cmd::followers::{self, FullList, SearchOpts, UserList};
+use crate::error::Error;
+use crate::twitter::ListRef;
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// IDs, URLs or `owner/slug`s of the lists to search members of
+    lists: Vec<ListRef>,
+    #[structopt(flatten)]
+    search: SearchOpts,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let lists = opts
+        .lists
+        .into_iter()
+        .map(FullList::ListMembers)
+        .map(UserList::Full)
+        .collect();
+    followers::search(opts.search, lists).await
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use diesel::{dsl::*, prelude::*};
+use structopt::StructOpt;
+
+use crate::common::connect_database;
+use crate::crypto::{self, Cipher, KeyOpts};
+use crate::error::Error;
+use crate::schema::*;
+use crate::store;
+
+/// Environment variable to read the new passphrase from.
+const NEW_PASSPHRASE_VAR: &str = "ABYSS_BLOCKER_NEW_PASSPHRASE";
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+    #[structopt(flatten)]
+    key: KeyOpts,
+    /// File containing the new key to encrypt the secrets with. If not specified, the new
+    /// passphrase is read from the `ABYSS_BLOCKER_NEW_PASSPHRASE` environment variable
+    #[structopt(long)]
+    new_key_file: Option<PathBuf>,
+    /// Store the secrets in plaintext instead of encrypting them with a new key
+    #[structopt(long, conflicts_with = "new-key-file")]
+    decrypt: bool,
+}
+
+pub fn run(opts: Opts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+    let cipher = opts.key.cipher(&conn)?;
+
+    let new = if opts.decrypt {
+        None
+    } else if let Some(material) =
+        crypto::key_material(opts.new_key_file.as_ref(), NEW_PASSPHRASE_VAR)?
+    {
+        let salt = crypto::new_salt();
+        let cipher = Cipher::derive(&material, &salt)?;
+        Some((salt, cipher))
+    } else {
+        return Err(Error::Input(format!(
+            "specify the new key with `--new-key-file` or `{}`, or `--decrypt`",
+            NEW_PASSPHRASE_VAR,
+        )));
+    };
+
+    // Secrets held in files, which are rewritten under the new key only after the new key has
+    // been committed, since the old one is lost then.
+    let mut staged = Vec::new();
+    let result = conn.transaction::<_, Error, _>(|| {
+        let rows = credentials::table
+            .select((
+                credentials::id,
+                credentials::identifier,
+                credentials::secret,
+            ))
+            .load::<(i32, String, String)>(&conn)?;
+        // Read every secret before anything is rewritten.
+        let mut secrets = Vec::with_capacity(rows.len());
+        for (id, identifier, stored) in rows {
+            let secret = store::get(&identifier, &stored, cipher.as_ref())?;
+            secrets.push((id, identifier, stored, secret));
+        }
+
+        delete(encryption::table).execute(&conn)?;
+        if let Some((ref salt, ref cipher)) = new {
+            insert_into(encryption::table)
+                .values((
+                    encryption::id.eq(0),
+                    encryption::salt.eq(salt),
+                    encryption::verifier.eq(cipher.verifier()),
+                ))
+                .execute(&conn)?;
+        }
+
+        let new_cipher = new.as_ref().map(|(_, cipher)| cipher);
+        for &(id, ref identifier, ref stored, ref secret) in &secrets {
+            // Keep each secret in the store holding it.
+            let (stored, file) = store::store_of(stored, new_cipher).stage(identifier, secret)?;
+            staged.push(file);
+            update(credentials::table.find(id))
+                .set(credentials::secret.eq(stored))
+                .execute(&conn)?;
+        }
+
+        Ok(secrets.len())
+    });
+    let n = match result {
+        Ok(n) => n,
+        Err(e) => {
+            for file in staged {
+                file.discard();
+            }
+            return Err(e);
+        }
+    };
+
+    for file in staged {
+        file.commit()?;
+    }
+
+    if opts.decrypt {
+        log::info!("Decrypted {} secrets", n);
+    } else {
+        log::info!("Encrypted {} secrets with the new key", n);
+    }
+
+    Ok(())
+}
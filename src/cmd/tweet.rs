@@ -0,0 +1,61 @@
+use structopt::StructOpt;
+
+use crate::cmd::followers::{self, IdList, SearchOpts, UserList};
+use crate::error::Error;
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// IDs or URLs of the tweets to search retweeters of
+    #[structopt(parse(try_from_str = parse_tweet))]
+    tweets: Vec<i64>,
+    #[structopt(flatten)]
+    search: SearchOpts,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let lists = opts
+        .tweets
+        .into_iter()
+        .map(IdList::Retweeters)
+        .map(UserList::Ids)
+        .collect();
+    followers::search(opts.search, lists).await
+}
+
+/// Parses a tweet ID or a URL like `https://twitter.com/<screen_name>/status/<id>`.
+fn parse_tweet(s: &str) -> Result<i64, String> {
+    if let Ok(id) = s.parse() {
+        return Ok(id);
+    }
+
+    // The ID follows `status`, which may be followed by more segments (e.g. `/photo/1`).
+    let path = s.split(&['?', '#'][..]).next().unwrap();
+    let mut segments = path.split('/');
+    segments
+        .position(|s| s == "status" || s == "statuses")
+        .and_then(|_| segments.next())
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("expected a tweet ID or URL: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tweet_forms() {
+        let id = 1050118621198921728;
+        assert_eq!(parse_tweet("1050118621198921728"), Ok(id));
+        let url = "https://twitter.com/user/status/1050118621198921728";
+        assert_eq!(parse_tweet(url), Ok(id));
+        assert_eq!(parse_tweet(&format!("{}/", url)), Ok(id));
+        assert_eq!(parse_tweet(&format!("{}?s=20#m", url)), Ok(id));
+        assert_eq!(parse_tweet(&format!("{}/photo/1", url)), Ok(id));
+        let url = "https://twitter.com/i/web/statuses/1050118621198921728";
+        assert_eq!(parse_tweet(url), Ok(id));
+
+        assert!(parse_tweet("https://twitter.com/user").is_err());
+        assert!(parse_tweet("https://twitter.com/user/status/").is_err());
+        assert!(parse_tweet("https://twitter.com/user/status/photo").is_err());
+    }
+}
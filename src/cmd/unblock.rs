@@ -0,0 +1,126 @@
+use diesel::{dsl::*, prelude::*};
+use structopt::StructOpt;
+
+use crate::action::Action;
+use crate::cmd::followers::{blocker, BlockerOpts};
+use crate::common::{connect_database, parse_date};
+use crate::crypto::KeyOpts;
+use crate::error::Error;
+use crate::query;
+use crate::schema::*;
+use crate::twitter::{self, Fetch};
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// User IDs of the users to unblock. If omitted, the users are selected from the users you
+    /// have blocked with this tool
+    users: Vec<i64>,
+    /// Unblock all the users you have blocked with this tool
+    #[structopt(long)]
+    all: bool,
+    /// Only unblock the users blocked before the date (Unix time or `YYYY-MM-DD`)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    before: Option<i64>,
+    /// Only unblock the users blocked at or after the date (Unix time or `YYYY-MM-DD`)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    after: Option<i64>,
+    /// Only unblock the users who no longer block you
+    #[structopt(long)]
+    not_blocked_by: bool,
+    /// Print the users to unblock without unblocking them
+    #[structopt(long)]
+    dry_run: bool,
+    /// User ID of the user to authorize
+    #[structopt(long)]
+    login: Option<i64>,
+    /// Path to the database
+    #[structopt(long, default_value = "db.sqlite3")]
+    database: String,
+    #[structopt(flatten)]
+    api: twitter::ApiOpts,
+    #[structopt(flatten)]
+    key: KeyOpts,
+    #[structopt(flatten)]
+    blocker: BlockerOpts,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let conn = connect_database(&opts.database)?;
+    let cipher = opts.key.cipher(&conn)?;
+
+    let http = opts.api.http();
+
+    // Authenticated user
+    let auth = query::authenticated_user(opts.login, &conn)?;
+
+    let tokens = query::credentials(auth, cipher.as_ref(), &conn)?;
+    if tokens.is_empty() {
+        return Err(Error::NoCredentials(auth));
+    }
+
+    let client = opts.blocker.client(tokens, &http);
+    query::load_rate_limits(client.limiter(), &conn)?;
+
+    let mut users = if !opts.users.is_empty() {
+        opts.users
+    } else if opts.all || opts.before.is_some() || opts.after.is_some() || opts.not_blocked_by {
+        let mut query = blocks::table
+            .select(blocks::target)
+            .filter(blocks::source.eq(auth))
+            .into_boxed();
+        if let Some(before) = opts.before {
+            query = query.filter(blocks::retrieved_at.lt(before));
+        }
+        if let Some(after) = opts.after {
+            query = query.filter(blocks::retrieved_at.ge(after));
+        }
+        query.load::<i64>(&conn)?
+    } else {
+        return Err(Error::Input(
+            "specify the users to unblock or a filter to select them (e.g. `--all`)".to_owned(),
+        ));
+    };
+
+    if opts.not_blocked_by {
+        let mut not_blocked_by = Vec::new();
+        for ids in users.chunks(100) {
+            log::info!("Looking up {} users", ids.len());
+            let looked_up = client
+                .users_lookup(ids)
+                .await
+                .map_err(|e| e.for_account(auth))?;
+            query::save_rate_limits(client.limiter(), &conn)?;
+            let looked_up = match looked_up {
+                Fetch::Ok(users) => users,
+                Fetch::Unavailable(_) => continue,
+            };
+            for u in looked_up {
+                if u.blocked_by {
+                    log::info!("User {} still blocks {}", u.id, auth);
+                } else {
+                    if !opts.dry_run {
+                        // Forget the block, which has been lifted.
+                        delete(blocks::table.find((u.id, auth))).execute(&conn)?;
+                    }
+                    not_blocked_by.push(u.id);
+                }
+            }
+        }
+        users = not_blocked_by;
+    }
+
+    if opts.dry_run {
+        for id in users {
+            println!("{}", id);
+        }
+        return Ok(());
+    }
+
+    query::queue_actions(Action::Unblock, auth, &users, &conn)?;
+
+    // `blocker` takes the actions queued in `pending_actions`, so no user has to be sent.
+    let rx = futures::stream::empty();
+    blocker(Action::Unblock, auth, rx, &opts.blocker, &client, &conn)
+        .await
+        .map_err(|e| e.for_account(auth))
+}
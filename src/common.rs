@@ -7,17 +7,11 @@ pub fn connect_database(uri: &str) -> ConnectionResult<SqliteConnection> {
     SqliteConnection::establish(uri)
 }
 
-pub fn instant_to_epoch(t: tokio::time::Instant) -> u64 {
-    let t = t.into_std();
-    let now_s = SystemTime::now();
-    let now_i = std::time::Instant::now();
-    let t_s = if t > now_i {
-        (now_s + (t - now_i))
-    } else {
-        (now_s - (now_i - t))
-    };
-    t_s.duration_since(UNIX_EPOCH)
-        .expect("`Instant` must be after Unix epoch")
+/// Returns the current Unix time.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time must be after Unix epoch")
         .as_secs()
 }
 
@@ -33,3 +27,85 @@ pub fn wait_until(until: u64) -> Delay {
         tokio::time::delay_until(tokio::time::Instant::now())
     }
 }
+
+/// Parses a Unix time or a date in `YYYY-MM-DD` format (at 00:00:00 UTC) into a Unix time.
+pub fn parse_date(s: &str) -> Result<i64, String> {
+    if let Ok(t) = s.parse() {
+        return Ok(t);
+    }
+
+    let err = || {
+        format!(
+            "expected a Unix time or a date in `YYYY-MM-DD` format: {}",
+            s
+        )
+    };
+
+    let mut split = s.splitn(3, '-');
+    let mut next = || {
+        split
+            .next()
+            .and_then(|s| s.parse::<i64>().ok())
+            .ok_or_else(err)
+    };
+    let (y, m, d) = (next()?, next()?, next()?);
+    if !(1..=12).contains(&m) || d < 1 || d > days_in_month(y, m) {
+        return Err(err());
+    }
+
+    Ok(days_from_civil(y, m, d) * 86400)
+}
+
+/// Formats a Unix time as a date in `YYYY-MM-DD` format (in UTC).
+pub fn format_date(t: i64) -> String {
+    // Civil from days algorithm: <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = t.div_euclid(86400) + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Returns the number of days in the month `m` (1-12) of the year `y`.
+fn days_in_month(y: i64, m: i64) -> i64 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days from 1970-01-01 to the date in the proleptic Gregorian calendar.
+pub fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    // Days from civil algorithm: <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_validates_day() {
+        assert_eq!(parse_date("1539129600"), Ok(1539129600));
+        assert_eq!(parse_date("2018-10-10"), Ok(1539129600));
+        assert_eq!(parse_date("2024-02-29"), Ok(1709164800));
+        assert!(parse_date("2026-02-29").is_err());
+        assert!(parse_date("2026-02-31").is_err());
+        assert!(parse_date("2100-02-29").is_err());
+        assert!(parse_date("2026-04-31").is_err());
+        assert!(parse_date("2026-13-01").is_err());
+        assert!(parse_date("2026-01-00").is_err());
+    }
+}
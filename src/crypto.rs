@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use diesel::prelude::*;
+use rand::RngCore;
+use structopt::StructOpt;
+
+use crate::error::Error;
+use crate::schema::encryption;
+
+/// Environment variable to read the passphrase from.
+pub const PASSPHRASE_VAR: &str = "ABYSS_BLOCKER_PASSPHRASE";
+
+/// Prefix of the encrypted secrets, which distinguishes them from plaintext ones.
+const PREFIX: &str = "enc:v1:";
+/// Plaintext of the verifier stored in the `encryption` table, used to check the key.
+const VERIFIER: &str = "abyss-blocker";
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+/// Options to specify the key to decrypt the secrets in the database.
+#[derive(StructOpt)]
+pub struct KeyOpts {
+    /// File containing the key to decrypt the secrets in the database with. If not specified,
+    /// the passphrase is read from the `ABYSS_BLOCKER_PASSPHRASE` environment variable
+    #[structopt(long)]
+    key_file: Option<PathBuf>,
+}
+
+impl KeyOpts {
+    /// Returns the cipher to decrypt (and encrypt) the secrets in the database, or `None` if the
+    /// secrets are not encrypted.
+    pub fn cipher(&self, conn: &SqliteConnection) -> Result<Option<Cipher>, Error> {
+        let material = key_material(self.key_file.as_ref(), PASSPHRASE_VAR)?;
+        let params = encryption::table
+            .select((encryption::salt, encryption::verifier))
+            .get_result::<(String, String)>(conn)
+            .optional()?;
+        match (material, params) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err(Error::Encryption(format!(
+                "the secrets in the database are encrypted; \
+                 specify the key with `--key-file` or `{}`",
+                PASSPHRASE_VAR,
+            ))),
+            (Some(_), None) => {
+                log::warn!(
+                    "The secrets in the database are not encrypted, so the key is ignored. \
+                     Run `rekey` to encrypt them"
+                );
+                Ok(None)
+            }
+            (Some(material), Some((salt, verifier))) => {
+                let cipher = Cipher::derive(&material, &salt)?;
+                cipher.verify(&verifier)?;
+                Ok(Some(cipher))
+            }
+        }
+    }
+}
+
+/// Reads the key material from `key_file` if specified, or else from the environment variable
+/// `var`.
+pub fn key_material(key_file: Option<&PathBuf>, var: &str) -> Result<Option<Vec<u8>>, Error> {
+    if let Some(path) = key_file {
+        return Ok(Some(std::fs::read(path)?));
+    }
+    match std::env::var(var) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(Some(passphrase.into_bytes())),
+        _ => Ok(None),
+    }
+}
+
+/// Encrypts and decrypts secrets with ChaCha20-Poly1305, using a key derived from a passphrase or
+/// the content of a key file with Argon2id.
+pub struct Cipher {
+    aead: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Derives the key from the key material and the salt stored in the `encryption` table.
+    pub fn derive(material: &[u8], salt: &str) -> Result<Self, Error> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(material, salt.as_bytes(), &mut key)
+            .map_err(|e| Error::Encryption(format!("unable to derive the key: {}", e)))?;
+        Ok(Cipher {
+            aead: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+
+    /// Encrypts a secret into the format stored in the database.
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .aead
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .expect("encryption must not fail");
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        format!("{}{}", PREFIX, base64::encode(&data))
+    }
+
+    /// Decrypts a secret encrypted by `encrypt`.
+    pub fn decrypt(&self, secret: &str) -> Result<String, Error> {
+        let data = secret
+            .strip_prefix(PREFIX)
+            .and_then(|data| base64::decode(data).ok())
+            .filter(|data| data.len() >= NONCE_LEN)
+            .ok_or_else(|| Error::Encryption("malformed encrypted secret".to_owned()))?;
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .aead
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Encryption("wrong passphrase or key file".to_owned()))?;
+        String::from_utf8(plaintext)
+            .map_err(|_| Error::Encryption("malformed encrypted secret".to_owned()))
+    }
+
+    /// Returns a verifier to be stored in the `encryption` table.
+    pub fn verifier(&self) -> String {
+        self.encrypt(VERIFIER)
+    }
+
+    /// Checks that the verifier stored in the `encryption` table was made with the same key.
+    fn verify(&self, verifier: &str) -> Result<(), Error> {
+        if self.decrypt(verifier)? == VERIFIER {
+            Ok(())
+        } else {
+            Err(Error::Encryption("wrong passphrase or key file".to_owned()))
+        }
+    }
+}
+
+/// Generates a random salt to be stored in the `encryption` table.
+pub fn new_salt() -> String {
+    let mut salt = [0; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    base64::encode(salt)
+}
+
+/// Returns the plaintext of a secret stored in the `credentials` table, which may or may not be
+/// encrypted.
+pub fn reveal(secret: String, cipher: Option<&Cipher>) -> Result<String, Error> {
+    if !secret.starts_with(PREFIX) {
+        return Ok(secret);
+    }
+    match cipher {
+        Some(cipher) => cipher.decrypt(&secret),
+        None => Err(Error::Encryption(format!(
+            "the secret is encrypted; specify the key with `--key-file` or `{}`",
+            PASSPHRASE_VAR,
+        ))),
+    }
+}
+
+/// Returns a secret in the format to be stored in the `credentials` table, which is encrypted
+/// if `cipher` is `Some`.
+pub fn seal(secret: &str, cipher: Option<&Cipher>) -> String {
+    match cipher {
+        Some(cipher) => cipher.encrypt(secret),
+        None => secret.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let salt = new_salt();
+        let cipher = Cipher::derive(b"passphrase", &salt).unwrap();
+
+        let secret = cipher.encrypt("token-secret");
+        assert!(secret.starts_with(PREFIX));
+        assert!(!secret.contains("token-secret"));
+        // The nonce is random for each encryption.
+        assert_ne!(cipher.encrypt("token-secret"), secret);
+        assert_eq!(cipher.decrypt(&secret).unwrap(), "token-secret");
+
+        assert_eq!(reveal(secret, Some(&cipher)).unwrap(), "token-secret");
+        assert_eq!(reveal("plaintext".to_owned(), None).unwrap(), "plaintext");
+        let sealed = seal("token-secret", Some(&cipher));
+        assert!(matches!(reveal(sealed, None), Err(Error::Encryption(_))));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let salt = new_salt();
+        let cipher = Cipher::derive(b"passphrase", &salt).unwrap();
+        let wrong = Cipher::derive(b"wrong passphrase", &salt).unwrap();
+
+        let secret = cipher.encrypt("token-secret");
+        assert!(matches!(wrong.decrypt(&secret), Err(Error::Encryption(_))));
+        assert!(matches!(
+            wrong.verify(&cipher.verifier()),
+            Err(Error::Encryption(_))
+        ));
+        assert!(cipher.verify(&cipher.verifier()).is_ok());
+        assert!(matches!(
+            cipher.decrypt("enc:v1:not base64"),
+            Err(Error::Encryption(_))
+        ));
+    }
+}
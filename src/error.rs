@@ -0,0 +1,140 @@
+use std::fmt::{self, Display};
+
+use crate::twitter::{ApiError, ErrorKind};
+
+/// The error type of the commands.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to open the database.
+    Connection(diesel::ConnectionError),
+    /// A database query failed.
+    Database(diesel::result::Error),
+    /// An HTTP request failed before a response was received.
+    Http(reqwest::Error),
+    /// Failed to decode a response body.
+    Json(serde_json::Error),
+    /// Malformed OAuth credentials or responses.
+    OAuth(String),
+    /// The API returned an error response.
+    Api(ApiError),
+    /// The API refused the credentials of the user.
+    Account { user: i64, error: ApiError },
+    /// No user was specified with `--login` and the default user has not been set.
+    NoUser,
+    /// No credentials are stored for the user.
+    NoCredentials(i64),
+    /// Invalid input from the user.
+    Input(String),
+    /// Failed to encrypt or decrypt the secrets in the database.
+    Encryption(String),
+    /// The secret store holding the secrets of the credentials is inaccessible.
+    Store(String),
+    /// Failed to read the standard input.
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Attributes an error caused by the credentials of `user` (an invalid token or a locked
+    /// account) to the user.
+    pub fn for_account(self, user: i64) -> Self {
+        match self {
+            Error::Api(error) => match error.kind() {
+                ErrorKind::InvalidToken | ErrorKind::AccountLocked => {
+                    Error::Account { user, error }
+                }
+                _ => Error::Api(error),
+            },
+            e => e,
+        }
+    }
+
+    /// Returns the exit code of the process for the error, which follows `sysexits.h`.
+    pub fn exit_code(&self) -> i32 {
+        const EX_USAGE: i32 = 64;
+        const EX_UNAVAILABLE: i32 = 69;
+        const EX_SOFTWARE: i32 = 70;
+        const EX_IOERR: i32 = 74;
+        const EX_TEMPFAIL: i32 = 75;
+        const EX_NOPERM: i32 = 77;
+
+        match *self {
+            Error::Connection(_) | Error::Database(_) | Error::Io(_) => EX_IOERR,
+            Error::Http(_) | Error::Store(_) => EX_UNAVAILABLE,
+            Error::Json(_) => EX_SOFTWARE,
+            Error::OAuth(_) | Error::NoCredentials(_) | Error::Encryption(_) => EX_NOPERM,
+            Error::Account { .. } => EX_NOPERM,
+            Error::Api(ref e) => match e.kind() {
+                ErrorKind::InvalidToken | ErrorKind::AccountLocked => EX_NOPERM,
+                ErrorKind::RateLimited => EX_TEMPFAIL,
+                _ if e.status.is_server_error() => EX_UNAVAILABLE,
+                _ => EX_SOFTWARE,
+            },
+            Error::NoUser | Error::Input(_) => EX_USAGE,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Connection(ref e) => write!(f, "unable to open the database: {}", e),
+            Error::Database(ref e) => write!(f, "database error: {}", e),
+            Error::Http(ref e) => write!(f, "HTTP error: {}", e),
+            Error::Json(ref e) => write!(f, "unable to decode the response: {}", e),
+            Error::OAuth(ref msg) => write!(f, "OAuth error: {}", msg),
+            Error::Api(ref e) => write!(f, "API error: {}", e),
+            Error::Account { user, ref error } => {
+                write!(f, "credentials of user {} are unusable: {}", user, error)
+            }
+            Error::NoUser => f.write_str("`--login` option or default user is required"),
+            Error::NoCredentials(user) => write!(f, "credentials not found for user: {}", user),
+            Error::Input(ref msg) => f.write_str(msg),
+            Error::Encryption(ref msg) => write!(f, "encryption error: {}", msg),
+            Error::Store(ref msg) => write!(f, "secret store error: {}", msg),
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Connection(ref e) => Some(e),
+            Error::Database(ref e) => Some(e),
+            Error::Http(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<diesel::ConnectionError> for Error {
+    fn from(e: diesel::ConnectionError) -> Self {
+        Error::Connection(e)
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
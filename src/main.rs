@@ -1,32 +1,68 @@
+// Old versions of the derive macros of `diesel` and `oauth1-request` emit non-local `impl`s.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 
 use structopt::StructOpt;
 
+mod action;
 mod auth;
 mod cmd;
 mod common;
+mod crypto;
+mod error;
 mod query;
 mod schema;
+mod store;
 mod twitter;
 
 #[derive(StructOpt)]
 enum Cmd {
+    #[structopt(about = "Manage the stored credentials")]
+    Accounts(cmd::accounts::Opts),
     #[structopt(about = "Register a set of API keys to the database")]
     Authorize(cmd::authorize::Opts),
+    #[structopt(about = "List the recorded blocks")]
+    Blocks(cmd::blocks::Opts),
     #[structopt(about = "Set the default user")]
     Default(cmd::default::Opts),
     #[structopt(about = "Search the list of followers of a user for users who blocks you")]
     Followers(cmd::followers::Opts),
+    #[structopt(about = "Search the list of users followed by a user for users who blocks you")]
+    Friends(cmd::friends::Opts),
+    #[structopt(about = "Search the members of a list for users who blocks you")]
+    ListMembers(cmd::list_members::Opts),
+    #[structopt(about = "Search the subscribers of a list for users who blocks you")]
+    ListSubscribers(cmd::list_subscribers::Opts),
+    #[structopt(about = "Encrypt the secrets in the database with a new key, or decrypt them")]
+    Rekey(cmd::rekey::Opts),
+    #[structopt(about = "Search the retweeters of a tweet for users who blocks you")]
+    Tweet(cmd::tweet::Opts),
+    #[structopt(about = "Unblock users")]
+    Unblock(cmd::unblock::Opts),
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
-    match Cmd::from_args() {
+    let result = match Cmd::from_args() {
+        Cmd::Accounts(opts) => cmd::accounts::run(opts).await,
         Cmd::Authorize(opts) => cmd::authorize::run(opts).await,
+        Cmd::Blocks(opts) => cmd::blocks::run(opts),
         Cmd::Default(opts) => cmd::default::run(opts),
         Cmd::Followers(opts) => cmd::followers::run(opts).await,
+        Cmd::Friends(opts) => cmd::friends::run(opts).await,
+        Cmd::ListMembers(opts) => cmd::list_members::run(opts).await,
+        Cmd::ListSubscribers(opts) => cmd::list_subscribers::run(opts).await,
+        Cmd::Rekey(opts) => cmd::rekey::run(opts),
+        Cmd::Tweet(opts) => cmd::tweet::run(opts).await,
+        Cmd::Unblock(opts) => cmd::unblock::run(opts).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
     }
 }
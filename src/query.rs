@@ -1,18 +1,38 @@
 use diesel::deserialize::FromSql;
 use diesel::prelude::*;
-use diesel::sql_types::{BigInt, Text};
-use diesel::{sql_query, Connection};
+use diesel::sql_types::{BigInt, Integer, Text};
+use diesel::{dsl::*, sql_query, Connection};
 
+use crate::action::Action;
 use crate::auth::Token;
-use crate::schema::default_user;
+use crate::common::now;
+use crate::crypto::Cipher;
+use crate::error::Error;
+use crate::schema::{
+    default_user, endpoints, pending_actions, rate_limits, tokens, user_profiles, users,
+};
+use crate::store;
+use crate::twitter::{RateLimit, RateLimiter, User};
 
-pub fn credentials<Conn>(user: i64, conn: &Conn) -> Option<Token>
+/// Returns all the tokens of `user`, retrieving the secrets from the secret stores holding them
+/// and decrypting them with `cipher` if they are encrypted.
+pub fn credentials<Conn>(
+    user: i64,
+    cipher: Option<&Cipher>,
+    conn: &Conn,
+) -> Result<Vec<Token>, Error>
 where
     Conn: Connection,
+    i32: FromSql<Integer, Conn::Backend>,
+    i64: FromSql<BigInt, Conn::Backend>,
     String: FromSql<Text, Conn::Backend>,
 {
     #[derive(QueryableByName)]
     struct QueriedToken {
+        #[sql_type = "Integer"]
+        id: i32,
+        #[sql_type = "BigInt"]
+        user: i64,
         #[sql_type = "Text"]
         client_identifier: String,
         #[sql_type = "Text"]
@@ -25,6 +45,8 @@ where
 
     sql_query(
         "SELECT \
+         tokens.id AS id, \
+         tokens.user AS user, \
          client.identifier AS client_identifier, \
          client.secret AS client_secret, \
          token.identifier AS token_identifier, \
@@ -33,26 +55,31 @@ where
          JOIN credentials AS client ON tokens.client = client.id \
          JOIN credentials AS token ON tokens.token = token.id \
          WHERE tokens.user = ? \
-         LIMIT 1",
+         ORDER BY tokens.id",
     )
     .bind::<BigInt, _>(user)
-    .get_result::<QueriedToken>(conn)
-    .optional()
-    .unwrap()
+    .load::<QueriedToken>(conn)?
+    .into_iter()
     .map(|t| {
         let client = oauth::Credentials {
+            secret: store::get(&t.client_identifier, &t.client_secret, cipher)?.into(),
             identifier: t.client_identifier.into(),
-            secret: t.client_secret.into(),
         };
         let token = oauth::Credentials {
+            secret: store::get(&t.token_identifier, &t.token_secret, cipher)?.into(),
             identifier: t.token_identifier.into(),
-            secret: t.token_secret.into(),
         };
-        Token { client, token }
+        Ok(Token {
+            id: t.id,
+            user: t.user,
+            client,
+            token,
+        })
     })
+    .collect()
 }
 
-pub fn default_user<Conn>(conn: &Conn) -> Option<i64>
+pub fn default_user<Conn>(conn: &Conn) -> QueryResult<Option<i64>>
 where
     Conn: Connection,
     i64: FromSql<BigInt, Conn::Backend>,
@@ -63,5 +90,247 @@ where
         .limit(1)
         .get_result(conn)
         .optional()
-        .unwrap()
+}
+
+/// Returns the users who have authorized tokens.
+pub fn accounts(conn: &SqliteConnection) -> QueryResult<Vec<i64>> {
+    tokens::table
+        .select(tokens::user)
+        .distinct()
+        .order(tokens::user)
+        .load(conn)
+}
+
+/// Returns the user specified by `login`, or the default user if `login` is `None`.
+pub fn authenticated_user(login: Option<i64>, conn: &SqliteConnection) -> Result<i64, Error> {
+    if let Some(id) = login {
+        Ok(id)
+    } else {
+        default_user(conn)?.ok_or(Error::NoUser)
+    }
+}
+
+/// Returns the ID of the `endpoints` row for `uri`, inserting the row if it does not exist yet.
+pub fn endpoint(uri: &str, conn: &SqliteConnection) -> QueryResult<i32> {
+    if let Some(id) = endpoints::table
+        .select(endpoints::id)
+        .filter(endpoints::uri.eq(uri))
+        .get_result::<i32>(conn)
+        .optional()?
+    {
+        Ok(id)
+    } else {
+        insert_into(endpoints::table)
+            .values(endpoints::uri.eq(uri))
+            .execute(conn)?;
+        endpoints::table
+            .select(endpoints::id)
+            .order(endpoints::id.desc())
+            .get_result::<i32>(conn)
+    }
+}
+
+/// Queues `action` on the users in `pending_actions` to be taken by `cmd::followers::blocker`.
+pub fn queue_actions(
+    action: Action,
+    auth: i64,
+    users: &[i64],
+    conn: &SqliteConnection,
+) -> QueryResult<()> {
+    if users.is_empty() {
+        return Ok(());
+    }
+    let values: Vec<_> = users
+        .iter()
+        .map(|&id| {
+            (
+                pending_actions::authenticated_user.eq(auth),
+                pending_actions::target.eq(id),
+                pending_actions::action.eq(action.as_str()),
+            )
+        })
+        .collect();
+    insert_or_ignore_into(pending_actions::table)
+        .values(values)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records the profiles of `users` in `user_profiles`, replacing the ones retrieved before.
+pub fn save_profiles(users: &[User], conn: &SqliteConnection) -> QueryResult<()> {
+    if users.is_empty() {
+        return Ok(());
+    }
+    let now = now() as i64;
+    let ids: Vec<_> = users.iter().map(|u| users::id.eq(u.id)).collect();
+    insert_or_ignore_into(users::table)
+        .values(ids)
+        .execute(conn)?;
+    let values: Vec<_> = users
+        .iter()
+        .map(|u| {
+            (
+                user_profiles::user.eq(u.id),
+                user_profiles::screen_name.eq(&u.screen_name),
+                user_profiles::name.eq(&u.name),
+                user_profiles::description.eq(&u.description),
+                user_profiles::location.eq(&u.location),
+                user_profiles::url.eq(&u.url),
+                user_profiles::created_at.eq(u.created_at),
+                user_profiles::followers_count.eq(u.followers_count),
+                user_profiles::friends_count.eq(u.friends_count),
+                user_profiles::statuses_count.eq(u.statuses_count),
+                user_profiles::protected.eq(u.protected),
+                user_profiles::verified.eq(u.verified),
+                user_profiles::default_profile_image.eq(u.default_profile_image),
+                user_profiles::updated_at.eq(now),
+            )
+        })
+        .collect();
+    replace_into(user_profiles::table)
+        .values(values)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Loads the rate limit status of the tokens of `limiter` persisted by previous runs, discarding
+/// the entries whose rate limit window has already been reset.
+pub fn load_rate_limits(limiter: &RateLimiter, conn: &SqliteConnection) -> QueryResult<()> {
+    let now = now() as i64;
+    delete(rate_limits::table.filter(rate_limits::reset.lt(now))).execute(conn)?;
+    let rows = rate_limits::table
+        .inner_join(endpoints::table)
+        .select((
+            rate_limits::token,
+            endpoints::uri,
+            rate_limits::remaining,
+            rate_limits::reset,
+        ))
+        .filter(rate_limits::token.eq_any(limiter.tokens()))
+        .load::<(i32, String, i64, i64)>(conn)?;
+    for (token, uri, remaining, reset) in rows {
+        let rl = RateLimit {
+            remaining: remaining as u64,
+            reset: reset as u64,
+        };
+        limiter.update(token, &uri, rl);
+    }
+    // The loaded entries need not be saved again.
+    limiter.take_updates();
+    Ok(())
+}
+
+/// Persists the rate limit status updated in `limiter` since the last call.
+pub fn save_rate_limits(limiter: &RateLimiter, conn: &SqliteConnection) -> QueryResult<()> {
+    for (token, uri, rl) in limiter.take_updates() {
+        replace_into(rate_limits::table)
+            .values((
+                rate_limits::endpoint.eq(endpoint(&uri, conn)?),
+                rate_limits::token.eq(token),
+                rate_limits::remaining.eq(rl.remaining as i64),
+                rate_limits::reset.eq(rl.reset as i64),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub use self::database::Database;
+
+#[cfg(test)]
+mod database {
+    use std::path::PathBuf;
+
+    use diesel::connection::SimpleConnection;
+    use diesel::dsl::*;
+    use diesel::prelude::*;
+
+    use crate::auth::Token;
+    use crate::common::connect_database;
+    use crate::schema::*;
+
+    /// A database file with the migrations run, which is removed on drop.
+    pub struct Database {
+        path: PathBuf,
+        pub conn: SqliteConnection,
+    }
+
+    impl Database {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "abyss-blocker-{}-{}.sqlite3",
+                name,
+                std::process::id(),
+            ));
+            let _ = std::fs::remove_file(&path);
+            let conn = connect_database(path.to_str().unwrap()).unwrap();
+
+            let mut migrations: Vec<_> =
+                std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+                    .unwrap()
+                    .map(|entry| entry.unwrap().path())
+                    .filter(|path| path.is_dir())
+                    .collect();
+            migrations.sort();
+            for dir in migrations {
+                let sql = std::fs::read_to_string(dir.join("up.sql")).unwrap();
+                conn.batch_execute(&sql).unwrap();
+            }
+
+            Database { path, conn }
+        }
+
+        pub fn path(&self) -> &str {
+            self.path.to_str().unwrap()
+        }
+
+        /// Stores `n` tokens of `user`.
+        pub fn authorize(&self, user: i64, n: usize) -> Vec<Token> {
+            let conn = &self.conn;
+            insert_or_ignore_into(users::table)
+                .values(users::id.eq(user))
+                .execute(conn)
+                .unwrap();
+            insert_or_ignore_into(credentials::table)
+                .values((
+                    credentials::identifier.eq("consumer"),
+                    credentials::secret.eq("consumer-secret"),
+                ))
+                .execute(conn)
+                .unwrap();
+            for i in 0..n {
+                let identifier = format!("{}-{}", user, i);
+                insert_into(credentials::table)
+                    .values((
+                        credentials::identifier.eq(&identifier),
+                        credentials::secret.eq("secret"),
+                    ))
+                    .execute(conn)
+                    .unwrap();
+                let id = |identifier: &str| {
+                    credentials::table
+                        .select(credentials::id)
+                        .filter(credentials::identifier.eq(identifier))
+                        .get_result::<i32>(conn)
+                        .unwrap()
+                };
+                insert_into(tokens::table)
+                    .values((
+                        tokens::client.eq(id("consumer")),
+                        tokens::token.eq(id(&identifier)),
+                        tokens::user.eq(user),
+                    ))
+                    .execute(conn)
+                    .unwrap();
+            }
+            super::credentials(user, None, conn).unwrap()
+        }
+    }
+
+    impl Drop for Database {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
 }
@@ -21,6 +21,14 @@ table! {
     }
 }
 
+table! {
+    encryption (id) {
+        id -> Integer,
+        salt -> Text,
+        verifier -> Text,
+    }
+}
+
 table! {
     endpoints (id) {
         id -> Integer,
@@ -28,6 +36,53 @@ table! {
     }
 }
 
+table! {
+    failed_actions (authenticated_user, target, action) {
+        authenticated_user -> BigInt,
+        target -> BigInt,
+        action -> Text,
+        attempts -> Integer,
+        status -> Nullable<Integer>,
+        code -> Nullable<Integer>,
+        error -> Text,
+        failed_at -> BigInt,
+    }
+}
+
+table! {
+    mutes (source, target) {
+        source -> BigInt,
+        target -> BigInt,
+        retrieved_at -> BigInt,
+    }
+}
+
+table! {
+    pending_actions (authenticated_user, target, action) {
+        authenticated_user -> BigInt,
+        target -> BigInt,
+        action -> Text,
+    }
+}
+
+table! {
+    pending_lookups (endpoint, authenticated_user, user, target) {
+        endpoint -> Integer,
+        authenticated_user -> BigInt,
+        user -> BigInt,
+        target -> BigInt,
+    }
+}
+
+table! {
+    rate_limits (endpoint, token) {
+        endpoint -> Integer,
+        token -> Integer,
+        remaining -> BigInt,
+        reset -> BigInt,
+    }
+}
+
 table! {
     tokens (id) {
         id -> Integer,
@@ -46,6 +101,25 @@ table! {
     }
 }
 
+table! {
+    user_profiles (user) {
+        user -> BigInt,
+        screen_name -> Text,
+        name -> Text,
+        description -> Text,
+        location -> Text,
+        url -> Nullable<Text>,
+        created_at -> BigInt,
+        followers_count -> BigInt,
+        friends_count -> BigInt,
+        statuses_count -> BigInt,
+        protected -> Bool,
+        verified -> Bool,
+        default_profile_image -> Bool,
+        updated_at -> BigInt,
+    }
+}
+
 table! {
     users (id) {
         id -> BigInt,
@@ -53,15 +127,26 @@ table! {
 }
 
 joinable!(default_user -> users (user));
+joinable!(pending_lookups -> endpoints (endpoint));
+joinable!(rate_limits -> endpoints (endpoint));
+joinable!(rate_limits -> tokens (token));
 joinable!(tokens -> users (user));
 joinable!(user_list_cursors -> endpoints (endpoint));
+joinable!(user_profiles -> users (user));
 
 allow_tables_to_appear_in_same_query!(
     blocks,
     credentials,
     default_user,
+    encryption,
     endpoints,
+    failed_actions,
+    mutes,
+    pending_actions,
+    pending_lookups,
+    rate_limits,
     tokens,
     user_list_cursors,
+    user_profiles,
     users,
 );
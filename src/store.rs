@@ -0,0 +1,385 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use crate::crypto::{self, Cipher};
+use crate::error::Error;
+
+/// Value of `credentials.secret` for the secrets stored in the OS keyring.
+const KEYRING: &str = "keyring:";
+/// Prefix of the values of `credentials.secret` for the secrets stored in a directory, which is
+/// followed by the path of the directory.
+const DIRECTORY: &str = "dir:";
+/// Service name of the keyring entries.
+const SERVICE: &str = "abyss-blocker";
+
+/// Options to select where to store the secrets of new credentials.
+#[derive(StructOpt)]
+pub struct StoreOpts {
+    /// Where to store the secrets of new credentials: `sqlite` (the database), `keyring` (the OS
+    /// keyring via the Secret Service API) or `dir:<path>` (a file per secret in the directory,
+    /// whose path must be absolute)
+    #[structopt(
+        long,
+        default_value = "sqlite",
+        env = "ABYSS_BLOCKER_SECRET_STORE",
+        hide_env_values = true
+    )]
+    secret_store: Backend,
+}
+
+impl StoreOpts {
+    /// Returns the store to put the secrets of new credentials into.
+    pub fn store<'a>(&'a self, cipher: Option<&'a Cipher>) -> Box<dyn SecretStore + 'a> {
+        match self.secret_store {
+            Backend::Sqlite => Box::new(Database { cipher }),
+            Backend::Keyring => Box::new(Keyring),
+            Backend::Directory(ref path) => Box::new(Directory { path, cipher }),
+        }
+    }
+}
+
+/// A backend to store the secrets of the credentials in.
+///
+/// The identifiers of the credentials are always stored in the `credentials` table, and the
+/// `credentials.secret` column holds either the secret itself or a reference to the backend
+/// holding it.
+pub trait SecretStore {
+    /// Stores the secret of the credentials and returns the value of `credentials.secret`.
+    fn put(&self, identifier: &str, secret: &str) -> Result<String, Error>;
+    /// Same as `put`, but a backend holding the secret in a file leaves the new content aside
+    /// until `Staged::commit` is called, so that the secret can be replaced after the new value of
+    /// `credentials.secret` has been committed.
+    fn stage(&self, identifier: &str, secret: &str) -> Result<(String, Staged), Error> {
+        Ok((self.put(identifier, secret)?, Staged::default()))
+    }
+    /// Retrieves the secret of the credentials whose `credentials.secret` column is `stored`.
+    fn get(&self, identifier: &str, stored: &str) -> Result<String, Error>;
+    /// Deletes the secret of the credentials from the backend.
+    fn delete(&self, identifier: &str) -> Result<(), Error>;
+}
+
+/// A secret written aside by `SecretStore::stage`.
+#[derive(Default)]
+pub struct Staged {
+    /// The file holding the new content and the file to replace with it.
+    rename: Option<(PathBuf, PathBuf)>,
+}
+
+impl Staged {
+    /// Replaces the secret with the new content.
+    pub fn commit(self) -> Result<(), Error> {
+        if let Some((from, to)) = self.rename {
+            fs::rename(&from, &to).map_err(|e| {
+                Error::Store(format!(
+                    "unable to move {} to {}: {}",
+                    from.display(),
+                    to.display(),
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Discards the new content, leaving the secret as is.
+    pub fn discard(self) {
+        if let Some((from, _)) = self.rename {
+            let _ = fs::remove_file(from);
+        }
+    }
+}
+
+/// Returns the store holding the secret of the credentials whose `credentials.secret` column is
+/// `secret`.
+pub fn store_of<'a>(secret: &'a str, cipher: Option<&'a Cipher>) -> Box<dyn SecretStore + 'a> {
+    if secret == KEYRING {
+        Box::new(Keyring)
+    } else if let Some(path) = secret.strip_prefix(DIRECTORY) {
+        Box::new(Directory {
+            path: Path::new(path),
+            cipher,
+        })
+    } else {
+        Box::new(Database { cipher })
+    }
+}
+
+/// Returns the secret of the credentials whose `credentials.secret` column is `secret`.
+pub fn get(identifier: &str, secret: &str, cipher: Option<&Cipher>) -> Result<String, Error> {
+    store_of(secret, cipher).get(identifier, secret)
+}
+
+enum Backend {
+    Sqlite,
+    Keyring,
+    Directory(PathBuf),
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "sqlite" => Ok(Backend::Sqlite),
+            "keyring" => Ok(Backend::Keyring),
+            _ => match s.strip_prefix(DIRECTORY) {
+                Some(path) if Path::new(path).is_absolute() => Ok(Backend::Directory(path.into())),
+                Some(path) if !path.is_empty() => Err(format!(
+                    "the path of the secret directory must be absolute: {}",
+                    path
+                )),
+                _ => Err(format!("unknown secret store: {}", s)),
+            },
+        }
+    }
+}
+
+/// The `credentials` table itself, where the secrets are encrypted if a key is given.
+struct Database<'a> {
+    cipher: Option<&'a Cipher>,
+}
+
+impl<'a> SecretStore for Database<'a> {
+    fn put(&self, _identifier: &str, secret: &str) -> Result<String, Error> {
+        Ok(crypto::seal(secret, self.cipher))
+    }
+
+    fn get(&self, _identifier: &str, stored: &str) -> Result<String, Error> {
+        crypto::reveal(stored.to_owned(), self.cipher)
+    }
+
+    fn delete(&self, _identifier: &str) -> Result<(), Error> {
+        // The secret is deleted along with the `credentials` row.
+        Ok(())
+    }
+}
+
+/// The OS keyring, accessed via the Secret Service API.
+struct Keyring;
+
+impl Keyring {
+    fn entry(identifier: &str) -> Result<keyring::Entry, Error> {
+        keyring::Entry::new(SERVICE, identifier).map_err(keyring_error)
+    }
+}
+
+impl SecretStore for Keyring {
+    fn put(&self, identifier: &str, secret: &str) -> Result<String, Error> {
+        Keyring::entry(identifier)?
+            .set_password(secret)
+            .map_err(keyring_error)?;
+        Ok(KEYRING.to_owned())
+    }
+
+    fn get(&self, identifier: &str, _stored: &str) -> Result<String, Error> {
+        Keyring::entry(identifier)?
+            .get_password()
+            .map_err(keyring_error)
+    }
+
+    fn delete(&self, identifier: &str) -> Result<(), Error> {
+        match Keyring::entry(identifier)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+}
+
+fn keyring_error(e: keyring::Error) -> Error {
+    Error::Store(format!("keyring: {}", e))
+}
+
+/// A directory with a file per secret, named after the identifier of the credentials, whose
+/// content is encrypted if a key is given.
+struct Directory<'a> {
+    path: &'a Path,
+    cipher: Option<&'a Cipher>,
+}
+
+impl<'a> Directory<'a> {
+    fn file(&self, identifier: &str) -> Result<PathBuf, Error> {
+        // A relative path would resolve against the working directory, which may differ between
+        // runs.
+        if !self.path.is_absolute() {
+            return Err(Error::Store(format!(
+                "the path of the secret directory must be absolute: {}",
+                self.path.display()
+            )));
+        }
+        // Guard against identifiers escaping the directory.
+        if identifier.is_empty() || identifier.contains(['/', '\\']) {
+            return Err(Error::Store(format!(
+                "unsupported credentials identifier: {}",
+                identifier
+            )));
+        }
+        Ok(self.path.join(identifier))
+    }
+
+    /// Writes the secret to `file` and returns the value of `credentials.secret`.
+    fn write(&self, file: &Path, secret: &str) -> Result<String, Error> {
+        let path = self
+            .path
+            .to_str()
+            .ok_or_else(|| Error::Store(format!("non UTF-8 path: {}", self.path.display())))?;
+        fs::create_dir_all(self.path)?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(file)?;
+        file.write_all(crypto::seal(secret, self.cipher).as_bytes())?;
+
+        Ok(format!("{}{}", DIRECTORY, path))
+    }
+}
+
+impl<'a> SecretStore for Directory<'a> {
+    fn put(&self, identifier: &str, secret: &str) -> Result<String, Error> {
+        self.write(&self.file(identifier)?, secret)
+    }
+
+    fn stage(&self, identifier: &str, secret: &str) -> Result<(String, Staged), Error> {
+        let file = self.file(identifier)?;
+        // OAuth keys and tokens never start with a dot, so the name does not clash with another
+        // secret.
+        let new = self.path.join(format!(".{}.new", identifier));
+        let stored = self.write(&new, secret)?;
+        let staged = Staged {
+            rename: Some((new, file)),
+        };
+        Ok((stored, staged))
+    }
+
+    fn get(&self, identifier: &str, _stored: &str) -> Result<String, Error> {
+        let secret = fs::read_to_string(self.file(identifier)?)?;
+        crypto::reveal(secret, self.cipher)
+    }
+
+    fn delete(&self, identifier: &str) -> Result<(), Error> {
+        match fs::remove_file(self.file(identifier)?) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary directory for a `Directory` store, which is removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir()
+                    .join(format!("abyss-blocker-{}-{}", name, std::process::id(),));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn directory_put_get() {
+        let dir = TempDir::new("directory_put_get");
+        let store = Directory {
+            path: &dir.0,
+            cipher: None,
+        };
+
+        let stored = store.put("1-token", "secret").unwrap();
+        assert_eq!(stored, format!("dir:{}", dir.0.display()));
+        assert_eq!(fs::read_to_string(dir.0.join("1-token")).unwrap(), "secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.0.join("1-token"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(get("1-token", &stored, None).unwrap(), "secret");
+
+        store.delete("1-token").unwrap();
+        assert!(get("1-token", &stored, None).is_err());
+        // Deleting a missing secret is not an error.
+        store.delete("1-token").unwrap();
+
+        assert!(matches!(
+            store.put("../1-token", "secret"),
+            Err(Error::Store(_))
+        ));
+    }
+
+    #[test]
+    fn directory_relative_path() {
+        assert!(matches!(
+            "dir:secrets".parse::<Backend>(),
+            Err(ref e) if e.contains("absolute")
+        ));
+        assert!("dir:".parse::<Backend>().is_err());
+        assert!(matches!(
+            get("1-token", "dir:secrets", None),
+            Err(Error::Store(_))
+        ));
+    }
+
+    #[test]
+    fn directory_encrypted() {
+        let dir = TempDir::new("directory_encrypted");
+        let cipher = Cipher::derive(b"passphrase", &crypto::new_salt()).unwrap();
+        let store = Directory {
+            path: &dir.0,
+            cipher: Some(&cipher),
+        };
+
+        let stored = store.put("1-token", "secret").unwrap();
+        let content = fs::read_to_string(dir.0.join("1-token")).unwrap();
+        assert_ne!(content, "secret");
+        assert_eq!(get("1-token", &stored, Some(&cipher)).unwrap(), "secret");
+        assert!(matches!(
+            get("1-token", &stored, None),
+            Err(Error::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn directory_stage() {
+        let dir = TempDir::new("directory_stage");
+        let store = Directory {
+            path: &dir.0,
+            cipher: None,
+        };
+        let stored = store.put("1-token", "old").unwrap();
+
+        // The secret is left as is until the staged content is committed.
+        let (staged, new) = store.stage("1-token", "new").unwrap();
+        assert_eq!(staged, stored);
+        assert_eq!(get("1-token", &stored, None).unwrap(), "old");
+        new.commit().unwrap();
+        assert_eq!(get("1-token", &stored, None).unwrap(), "new");
+
+        let (_, new) = store.stage("1-token", "discarded").unwrap();
+        new.discard();
+        assert_eq!(get("1-token", &stored, None).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+}
@@ -1,12 +1,22 @@
 mod api;
+mod client;
 mod models;
+mod rate_limiter;
+mod transport;
 
 pub use api::*;
+pub use client::*;
 pub use models::*;
+pub use rate_limiter::*;
+pub use transport::*;
+
+use std::fmt::{self, Display};
 
 use atoi::atoi;
 use reqwest::header::{HeaderMap, HeaderName};
+use reqwest::StatusCode;
 
+#[derive(Clone, Copy, Debug)]
 pub struct RateLimit {
     pub remaining: u64,
     pub reset: u64,
@@ -24,3 +34,157 @@ pub fn rate_limit(headers: &HeaderMap) -> Option<RateLimit> {
         })
     })
 }
+
+/// An error response of the API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// Errors in the response body, which is empty if the body is not in the expected format.
+    pub errors: Vec<ErrorMessage>,
+    /// The response body if it is not in the expected format.
+    pub body: String,
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// Classification of `ApiError`s by how they should be handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The access token is invalid or expired.
+    InvalidToken,
+    /// The authenticated user's account is temporarily locked.
+    AccountLocked,
+    RateLimited,
+    /// The user or resource is not found or suspended.
+    NotFound,
+    /// The authenticated user is blocked from the action.
+    Blocked,
+    /// The authenticated user is not authorized to view the resource (e.g. the followers of a
+    /// protected account).
+    NotAuthorized,
+    Other,
+}
+
+impl ApiError {
+    /// Reads the body of an error response.
+    pub fn from_response(response: &Response) -> Self {
+        let (errors, body) = match serde_json::from_slice::<Errors>(&response.body) {
+            Ok(errors) => (errors.errors, String::new()),
+            Err(_) => (
+                Vec::new(),
+                String::from_utf8_lossy(&response.body).into_owned(),
+            ),
+        };
+        ApiError {
+            status: response.status,
+            errors,
+            body,
+            rate_limit: rate_limit(&response.headers),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        for e in &self.errors {
+            match e.code {
+                32 | 89 => return ErrorKind::InvalidToken,
+                326 => return ErrorKind::AccountLocked,
+                88 => return ErrorKind::RateLimited,
+                50 | 63 => return ErrorKind::NotFound,
+                136 | 162 => return ErrorKind::Blocked,
+                _ => {}
+            }
+        }
+
+        // A 401 response without an error code is not specific to the token: it is also returned
+        // for the resources of a protected account.
+        match self.status {
+            StatusCode::UNAUTHORIZED => ErrorKind::NotAuthorized,
+            StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimited,
+            StatusCode::NOT_FOUND => ErrorKind::NotFound,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status)?;
+        if self.errors.is_empty() {
+            if !self.body.is_empty() {
+                write!(f, ": {}", self.body)?;
+            }
+        } else {
+            for (i, e) in self.errors.iter().enumerate() {
+                let sep = if i == 0 { ":" } else { ";" };
+                write!(f, "{} {} (code {})", sep, e.message, e.code)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(status: u16, body: &str) -> ApiError {
+        ApiError::from_response(&Response {
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: HeaderMap::new(),
+            body: body.as_bytes().to_vec(),
+        })
+    }
+
+    fn code(status: u16, code: u32) -> ApiError {
+        let body = format!(r#"{{"errors":[{{"code":{},"message":"Error"}}]}}"#, code);
+        error(status, &body)
+    }
+
+    #[test]
+    fn error_kind_by_code() {
+        let table = [
+            (401, 89, ErrorKind::InvalidToken),
+            (401, 32, ErrorKind::InvalidToken),
+            (403, 326, ErrorKind::AccountLocked),
+            (429, 88, ErrorKind::RateLimited),
+            (404, 50, ErrorKind::NotFound),
+            (403, 63, ErrorKind::NotFound),
+            (403, 136, ErrorKind::Blocked),
+            (403, 162, ErrorKind::Blocked),
+            (403, 161, ErrorKind::Other),
+        ];
+        for &(status, c, kind) in &table {
+            assert_eq!(code(status, c).kind(), kind, "code {}", c);
+        }
+    }
+
+    #[test]
+    fn error_kind_by_status() {
+        let table = [
+            (401, ErrorKind::NotAuthorized),
+            (429, ErrorKind::RateLimited),
+            (404, ErrorKind::NotFound),
+            (500, ErrorKind::Other),
+        ];
+        for &(status, kind) in &table {
+            assert_eq!(error(status, "").kind(), kind, "status {}", status);
+        }
+        // An unknown code falls back to the status.
+        assert_eq!(code(404, 34).kind(), ErrorKind::NotFound);
+
+        // The response for the followers of a protected account
+        let body = r#"{"request":"/1.1/followers/list.json","error":"Not authorized."}"#;
+        assert_eq!(error(401, body).kind(), ErrorKind::NotAuthorized);
+    }
+
+    #[test]
+    fn error_kind_first_known_code() {
+        let body = r#"{"errors":[{"code":34,"message":"A"},{"code":136,"message":"B"}]}"#;
+        let e = error(403, body);
+        assert_eq!(e.kind(), ErrorKind::Blocked);
+        assert_eq!(e.to_string(), "403 Forbidden: A (code 34); B (code 136)");
+
+        let e = error(502, "Bad Gateway");
+        assert!(e.errors.is_empty());
+        assert_eq!(e.to_string(), "502 Bad Gateway: Bad Gateway");
+    }
+}
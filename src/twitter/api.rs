@@ -1,7 +1,24 @@
+/// Base URL of the API, which all the endpoint URIs below start with.
+pub const API_BASE: &str = "https://api.twitter.com";
+
 pub const ACCOUNT_VERIFY_CREDENTIALS: &str =
     "https://api.twitter.com/1.1/account/verify_credentials.json";
 pub const BLOCKS_CREATE: &str = "https://api.twitter.com/1.1/blocks/create.json";
+pub const BLOCKS_DESTROY: &str = "https://api.twitter.com/1.1/blocks/destroy.json";
+pub const FOLLOWERS_IDS: &str = "https://api.twitter.com/1.1/followers/ids.json";
 pub const FOLLOWERS_LIST: &str = "https://api.twitter.com/1.1/followers/list.json";
+pub const FRIENDS_LIST: &str = "https://api.twitter.com/1.1/friends/list.json";
+pub const LISTS_MEMBERS: &str = "https://api.twitter.com/1.1/lists/members.json";
+pub const LISTS_SHOW: &str = "https://api.twitter.com/1.1/lists/show.json";
+pub const LISTS_SUBSCRIBERS: &str = "https://api.twitter.com/1.1/lists/subscribers.json";
+pub const MUTES_USERS_CREATE: &str = "https://api.twitter.com/1.1/mutes/users/create.json";
+pub const OAUTH_ACCESS_TOKEN: &str = "https://api.twitter.com/oauth/access_token";
+pub const OAUTH_AUTHORIZE: &str = "https://api.twitter.com/oauth/authorize";
+pub const OAUTH_INVALIDATE_TOKEN: &str = "https://api.twitter.com/1.1/oauth/invalidate_token";
+pub const OAUTH_REQUEST_TOKEN: &str = "https://api.twitter.com/oauth/request_token";
+pub const STATUSES_RETWEETERS_IDS: &str =
+    "https://api.twitter.com/1.1/statuses/retweeters/ids.json";
+pub const USERS_LOOKUP: &str = "https://api.twitter.com/1.1/users/lookup.json";
 
 #[derive(oauth::Authorize)]
 pub struct AccountVerifyCredentials {
@@ -17,6 +34,21 @@ pub struct BlocksCreate {
     pub skip_status: bool,
 }
 
+#[derive(oauth::Authorize)]
+pub struct BlocksDestroy {
+    pub user_id: i64,
+    pub include_entities: bool,
+    pub skip_status: bool,
+}
+
+#[derive(oauth::Authorize)]
+pub struct FollowersIds {
+    pub user_id: i64,
+    pub count: u64,
+    pub cursor: i64,
+    pub stringify_ids: bool,
+}
+
 #[derive(oauth::Authorize)]
 pub struct FollowersList {
     pub user_id: i64,
@@ -25,3 +57,51 @@ pub struct FollowersList {
     pub include_user_entities: bool,
     pub cursor: i64,
 }
+
+#[derive(oauth::Authorize)]
+pub struct FriendsList {
+    pub user_id: i64,
+    pub count: u64,
+    pub skip_status: bool,
+    pub include_user_entities: bool,
+    pub cursor: i64,
+}
+
+/// Parameters of `lists/members` and `lists/subscribers`, which share the same set of parameters.
+#[derive(oauth::Authorize)]
+pub struct ListsMembers<'a> {
+    pub list_id: Option<i64>,
+    pub owner_screen_name: Option<&'a str>,
+    pub slug: Option<&'a str>,
+    pub count: u64,
+    pub cursor: i64,
+    pub include_entities: bool,
+    pub skip_status: bool,
+}
+
+#[derive(oauth::Authorize)]
+pub struct ListsShow<'a> {
+    pub list_id: Option<i64>,
+    pub owner_screen_name: Option<&'a str>,
+    pub slug: Option<&'a str>,
+}
+
+#[derive(oauth::Authorize)]
+pub struct MutesUsersCreate {
+    pub user_id: i64,
+}
+
+#[derive(oauth::Authorize)]
+pub struct StatusesRetweetersIds {
+    pub id: i64,
+    pub count: u64,
+    pub cursor: i64,
+    pub stringify_ids: bool,
+}
+
+#[derive(oauth::Authorize)]
+pub struct UsersLookup<'a> {
+    /// Comma-separated list of up to 100 user IDs.
+    pub user_id: &'a str,
+    pub include_entities: bool,
+}
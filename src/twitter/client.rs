@@ -0,0 +1,396 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+
+use super::*;
+use crate::auth::Token;
+use crate::error::Error;
+
+/// A client of the API on behalf of a set of tokens.
+///
+/// The requests are spread over the tokens within their rate limits, which are tracked by the
+/// client's `RateLimiter`.
+pub struct Client {
+    tokens: Vec<Token>,
+    http: Http,
+    limiter: RateLimiter,
+}
+
+/// Outcome of a `GET` request to the API.
+pub enum Fetch<T> {
+    Ok(T),
+    /// The resource is unavailable to the authenticated user (i.e. not found or suspended, or the
+    /// user is blocked from it).
+    Unavailable(ApiError),
+}
+
+/// A reference to a Twitter List, either by its ID or by `owner/slug`.
+#[derive(Clone)]
+pub enum ListRef {
+    Id(i64),
+    Slug {
+        owner_screen_name: String,
+        slug: String,
+    },
+}
+
+impl Client {
+    /// Creates a client signing the requests with `tokens`, allowing at most `max_in_flight`
+    /// concurrent requests.
+    pub fn new(tokens: Vec<Token>, http: Http, max_in_flight: usize) -> Self {
+        let limiter = RateLimiter::new(tokens.iter().map(|t| t.id).collect(), max_in_flight);
+        Client {
+            tokens,
+            http,
+            limiter,
+        }
+    }
+
+    /// Creates a client signing the requests with `tokens`, which shares the rate limit status of
+    /// the tokens with this client (see `RateLimiter::share`).
+    pub fn share(&self, tokens: Vec<Token>, max_in_flight: usize) -> Self {
+        let limiter = self
+            .limiter
+            .share(tokens.iter().map(|t| t.id).collect(), max_in_flight);
+        Client {
+            tokens,
+            http: self.http.clone(),
+            limiter,
+        }
+    }
+
+    /// Returns the rate limit tracker of the tokens, whose status should be loaded and persisted
+    /// with `query::load_rate_limits` and `query::save_rate_limits`.
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    pub async fn followers_ids(&self, user_id: i64, cursor: i64) -> Result<Fetch<Ids>, Error> {
+        let params = FollowersIds {
+            user_id,
+            count: 5000,
+            cursor,
+            stringify_ids: false,
+        };
+        self.get(FOLLOWERS_IDS, params).await
+    }
+
+    pub async fn followers_list(&self, user_id: i64, cursor: i64) -> Result<Fetch<Users>, Error> {
+        let params = FollowersList {
+            user_id,
+            count: 200,
+            skip_status: true,
+            include_user_entities: false,
+            cursor,
+        };
+        self.get(FOLLOWERS_LIST, params).await
+    }
+
+    pub async fn friends_list(&self, user_id: i64, cursor: i64) -> Result<Fetch<Users>, Error> {
+        let params = FriendsList {
+            user_id,
+            count: 200,
+            skip_status: true,
+            include_user_entities: false,
+            cursor,
+        };
+        self.get(FRIENDS_LIST, params).await
+    }
+
+    pub async fn lists_members(&self, list: &ListRef, cursor: i64) -> Result<Fetch<Users>, Error> {
+        self.get(LISTS_MEMBERS, list.members(cursor)).await
+    }
+
+    pub async fn lists_show(&self, list: &ListRef) -> Result<Fetch<List>, Error> {
+        let (list_id, owner_screen_name, slug) = list.params();
+        let params = ListsShow {
+            list_id,
+            owner_screen_name,
+            slug,
+        };
+        self.get(LISTS_SHOW, params).await
+    }
+
+    pub async fn lists_subscribers(
+        &self,
+        list: &ListRef,
+        cursor: i64,
+    ) -> Result<Fetch<Users>, Error> {
+        self.get(LISTS_SUBSCRIBERS, list.members(cursor)).await
+    }
+
+    pub async fn statuses_retweeters_ids(&self, id: i64, cursor: i64) -> Result<Fetch<Ids>, Error> {
+        let params = StatusesRetweetersIds {
+            id,
+            count: 100,
+            cursor,
+            stringify_ids: false,
+        };
+        self.get(STATUSES_RETWEETERS_IDS, params).await
+    }
+
+    /// Retrieves up to 100 users.
+    pub async fn users_lookup(&self, ids: &[i64]) -> Result<Fetch<Vec<User>>, Error> {
+        let user_id = ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let params = UsersLookup {
+            user_id: &user_id,
+            include_entities: false,
+        };
+        self.get(USERS_LOOKUP, params).await
+    }
+
+    /// Blocks the user with the token of `permit`, which is held until the response arrives.
+    pub async fn blocks_create(&self, permit: Permit<'_>, user_id: i64) -> Result<(), Error> {
+        let params = BlocksCreate {
+            user_id,
+            include_entities: false,
+            skip_status: true,
+        };
+        self.act(permit, BLOCKS_CREATE, params).await
+    }
+
+    /// Unblocks the user with the token of `permit`, which is held until the response arrives.
+    pub async fn blocks_destroy(&self, permit: Permit<'_>, user_id: i64) -> Result<(), Error> {
+        let params = BlocksDestroy {
+            user_id,
+            include_entities: false,
+            skip_status: true,
+        };
+        self.act(permit, BLOCKS_DESTROY, params).await
+    }
+
+    /// Mutes the user with the token of `permit`, which is held until the response arrives.
+    pub async fn mutes_users_create(&self, permit: Permit<'_>, user_id: i64) -> Result<(), Error> {
+        self.act(permit, MUTES_USERS_CREATE, MutesUsersCreate { user_id })
+            .await
+    }
+
+    /// Returns the profile of the user of one of the tokens, failing with `Error::Account` if the
+    /// token is unusable.
+    pub async fn verify_credentials(&self) -> Result<User, Error> {
+        let params = AccountVerifyCredentials {
+            include_entities: false,
+            skip_status: true,
+            include_email: false,
+        };
+        let permit = self.limiter.acquire(ACCOUNT_VERIFY_CREDENTIALS).await;
+        let token = &self.tokens[permit.token()];
+        let request = self.sign(token, ACCOUNT_VERIFY_CREDENTIALS, params);
+        let response = self.http.get(request).await?;
+        permit.update(rate_limit(&response.headers));
+
+        if !response.status.is_success() {
+            let e = ApiError::from_response(&response);
+            // The credentials of the authenticated user are the only resource of the endpoint.
+            if e.status == StatusCode::UNAUTHORIZED {
+                return Err(Error::Account {
+                    user: token.user,
+                    error: e,
+                });
+            }
+            return Err(Error::Api(e).for_account(token.user));
+        }
+
+        response.json()
+    }
+
+    /// Invalidates all the tokens with `oauth/invalidate_token`.
+    pub async fn invalidate_token(&self) -> Result<(), Error> {
+        for token in &self.tokens {
+            let request = oauth::Builder::new(token.client(), oauth::HmacSha1)
+                .token(token.token())
+                .post_form(self.http.uri(OAUTH_INVALIDATE_TOKEN), ());
+            let response = self.http.post_form(OAUTH_INVALIDATE_TOKEN, request).await?;
+
+            if !response.status.is_success() {
+                let e = ApiError::from_response(&response);
+                return Err(Error::Api(e).for_account(token.user));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Signs a `GET` request to `endpoint` with `token`.
+    fn sign(&self, token: &Token, endpoint: &str, params: impl oauth::Authorize) -> oauth::Request {
+        oauth::Builder::new(token.client(), oauth::HmacSha1)
+            .token(token.token())
+            .get(self.http.uri(endpoint), params)
+    }
+
+    /// Sends a `GET` request to `endpoint` and deserializes the response.
+    ///
+    /// The request is signed with one of the tokens that has budget left in the rate limit of
+    /// `endpoint`. On a rate limit error, retries with a newly signed request, switching to
+    /// another token or waiting for the rate limit to reset.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        params: impl oauth::Authorize,
+    ) -> Result<Fetch<T>, Error> {
+        loop {
+            let permit = self.limiter.acquire(endpoint).await;
+            let token = &self.tokens[permit.token()];
+            let response = self.http.get(self.sign(token, endpoint, &params)).await?;
+            permit.update(rate_limit(&response.headers));
+            if response.status.is_success() {
+                return Ok(Fetch::Ok(response.json()?));
+            }
+
+            let e = ApiError::from_response(&response);
+            match (e.kind(), e.rate_limit) {
+                // `limiter` switches to another token or waits for the rate limit to reset before
+                // the next attempt.
+                (ErrorKind::RateLimited, Some(_)) => log::warn!("Got a rate limit error"),
+                (ErrorKind::NotFound, _)
+                | (ErrorKind::Blocked, _)
+                | (ErrorKind::NotAuthorized, _) => {
+                    return Ok(Fetch::Unavailable(e));
+                }
+                _ => return Err(Error::Api(e).for_account(token.user)),
+            }
+        }
+    }
+
+    /// Sends a `POST` request to take an action on a user with the token of `permit`.
+    ///
+    /// Unlike `get`, an error response is returned as `Error::Api` as is, and is left to the
+    /// caller to be retried.
+    async fn act(
+        &self,
+        permit: Permit<'_>,
+        endpoint: &'static str,
+        params: impl oauth::Authorize,
+    ) -> Result<(), Error> {
+        let token = &self.tokens[permit.token()];
+        let request = oauth::Builder::new(token.client(), oauth::HmacSha1)
+            .token(token.token())
+            .post_form(self.http.uri(endpoint), params);
+        let response = self.http.post_form(endpoint, request).await?;
+        permit.update(rate_limit(&response.headers));
+        if response.status.is_success() {
+            Ok(())
+        } else {
+            Err(Error::Api(ApiError::from_response(&response)))
+        }
+    }
+}
+
+impl ListRef {
+    /// Returns the `list_id`, `owner_screen_name` and `slug` parameters to identify the list.
+    fn params(&self) -> (Option<i64>, Option<&str>, Option<&str>) {
+        match *self {
+            ListRef::Id(id) => (Some(id), None, None),
+            ListRef::Slug {
+                ref owner_screen_name,
+                ref slug,
+            } => (None, Some(owner_screen_name), Some(slug)),
+        }
+    }
+
+    /// Returns the parameters of `lists/members` and `lists/subscribers`.
+    fn members(&self, cursor: i64) -> ListsMembers<'_> {
+        let (list_id, owner_screen_name, slug) = self.params();
+        ListsMembers {
+            list_id,
+            owner_screen_name,
+            slug,
+            count: 5000,
+            cursor,
+            include_entities: false,
+            skip_status: true,
+        }
+    }
+}
+
+impl FromStr for ListRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if let Ok(id) = s.parse() {
+            return Ok(ListRef::Id(id));
+        }
+
+        let err = || format!("expected a list ID, URL or `owner/slug`: {}", s);
+
+        // A URL like `https://twitter.com/i/lists/<id>`
+        let path = s.split(&['?', '#'][..]).next().unwrap();
+        let segments: Vec<_> = path.split('/').collect();
+        if let Some(i) = segments.windows(2).position(|w| w == ["i", "lists"]) {
+            return segments
+                .get(i + 2)
+                .and_then(|id| id.parse().ok())
+                .map(ListRef::Id)
+                .ok_or_else(err);
+        }
+
+        let mut split = s.splitn(2, '/');
+        let owner = split.next().unwrap().trim().trim_start_matches('@');
+        let slug = split.next().map(str::trim).unwrap_or("");
+        if owner.is_empty() || slug.is_empty() || slug.contains('/') {
+            return Err(err());
+        }
+        Ok(ListRef::Slug {
+            owner_screen_name: owner.to_owned(),
+            slug: slug.to_owned(),
+        })
+    }
+}
+
+impl Display for ListRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ListRef::Id(id) => write!(f, "{}", id),
+            ListRef::Slug {
+                ref owner_screen_name,
+                ref slug,
+            } => write!(f, "{}/{}", owner_screen_name, slug),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<ListRef, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parse_list_ref() {
+        assert!(matches!(parse("123"), Ok(ListRef::Id(123))));
+        assert!(matches!(
+            parse("https://twitter.com/i/lists/123"),
+            Ok(ListRef::Id(123))
+        ));
+        assert!(matches!(
+            parse("https://x.com/i/lists/123/members?s=20"),
+            Ok(ListRef::Id(123))
+        ));
+        match parse("@owner/slug").unwrap() {
+            ListRef::Slug {
+                owner_screen_name,
+                slug,
+            } => assert_eq!((&*owner_screen_name, &*slug), ("owner", "slug")),
+            ListRef::Id(id) => panic!("expected a slug, got ID {}", id),
+        }
+
+        let invalid = [
+            "https://twitter.com/i/lists/",
+            "https://twitter.com/owner/lists/slug",
+            "owner/",
+            "owner/ ",
+            "@/slug",
+            "slug",
+        ];
+        for &s in &invalid {
+            assert!(parse(s).is_err(), "{}", s);
+        }
+    }
+}
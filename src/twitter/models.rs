@@ -1,17 +1,158 @@
+use serde::de::{self, Deserializer};
 use serde::Deserialize;
 
+use crate::common::days_from_civil;
+
+/// The body of an `oauth/access_token` response.
+#[derive(Debug, Deserialize)]
+pub struct AccessToken {
+    pub oauth_token: String,
+    pub oauth_token_secret: String,
+    pub user_id: i64,
+    pub screen_name: String,
+}
+
+/// The body of an error response.
+#[derive(Debug, Deserialize)]
+pub struct Errors {
+    pub errors: Vec<ErrorMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorMessage {
+    pub code: u32,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ids {
+    pub ids: Vec<i64>,
+    pub next_cursor: i64,
+    #[allow(dead_code)]
+    pub previous_cursor: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct List {
+    pub id: i64,
+}
+
+/// The body of an `oauth/request_token` response.
+#[derive(Debug, Deserialize)]
+pub struct RequestToken {
+    pub oauth_token: String,
+    pub oauth_token_secret: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Users {
     pub users: Vec<User>,
     pub next_cursor: i64,
+    #[allow(dead_code)]
     pub previous_cursor: i64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct User {
     pub id: i64,
+    pub screen_name: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub location: String,
+    pub url: Option<String>,
+    /// Unix time of the creation of the account.
+    #[serde(deserialize_with = "de_created_at")]
+    pub created_at: i64,
+    pub followers_count: i64,
+    pub friends_count: i64,
+    pub statuses_count: i64,
+    pub protected: bool,
+    pub verified: bool,
+    pub default_profile_image: bool,
     /// An undocumented attribute that indicates whether the authenticated user is blocks this user.
+    ///
+    /// This and `blocked_by` are absent from the authenticated user's own object.
+    #[serde(default)]
     pub blocking: bool,
     /// An undocumented attribute that indicates whether the authenticated user is blocked by this user.
+    #[serde(default)]
     pub blocked_by: bool,
+    /// An undocumented attribute that indicates whether the authenticated user mutes this user.
+    #[serde(default)]
+    pub muting: bool,
+}
+
+/// Deserializes a `created_at` attribute like `Wed Oct 10 20:19:24 +0000 2018` into a Unix time.
+fn de_created_at<'de, D: Deserializer<'de>>(d: D) -> Result<i64, D::Error> {
+    let s = <&str>::deserialize(d)?;
+    parse_created_at(s)
+        .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(s), &"a `created_at` date"))
+}
+
+fn parse_created_at(s: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut fields = s.split(' ');
+    let _weekday = fields.next()?;
+    let month = fields.next()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let day: i64 = fields.next()?.parse().ok()?;
+    let mut time = fields.next()?.split(':').map(|t| t.parse::<i64>().ok());
+    let (h, m, sec) = (time.next()??, time.next()??, time.next()??);
+    let offset = fields.next()?;
+    let year: i64 = fields.next()?.parse().ok()?;
+
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let offset_h: i64 = offset.get(1..3)?.parse().ok()?;
+    let offset_m: i64 = offset.get(3..5)?.parse().ok()?;
+    let offset = sign * (offset_h * 3600 + offset_m * 60);
+
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + h * 3600 + m * 60 + sec - offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_created_at_offsets() {
+        let utc = 1539202764;
+        assert_eq!(
+            parse_created_at("Wed Oct 10 20:19:24 +0000 2018"),
+            Some(utc)
+        );
+        assert_eq!(
+            parse_created_at("Wed Oct 10 20:19:24 +0900 2018"),
+            Some(utc - 9 * 3600),
+        );
+        assert_eq!(
+            parse_created_at("Wed Oct 10 20:19:24 -0530 2018"),
+            Some(utc + 5 * 3600 + 30 * 60),
+        );
+    }
+
+    #[test]
+    fn parse_created_at_malformed() {
+        for s in &[
+            "",
+            "Wed Oct 10 20:19:24 2018",
+            "Wed Foo 10 20:19:24 +0000 2018",
+            "Wed Oct 10 20:19 +0000 2018",
+            "Wed Oct 10 20:19:24 0000 2018",
+            "Wed Oct 10 20:19:24 +00 2018",
+            "Wed Oct 10 20:19:24 +0000",
+            "2018-10-10T20:19:24Z",
+        ] {
+            assert_eq!(parse_created_at(s), None, "{:?}", s);
+        }
+    }
 }
@@ -0,0 +1,418 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+use super::RateLimit;
+use crate::common::{now, wait_until};
+
+/// Tracks the rate limit status of each endpoint and the requests in flight, so that requests
+/// are dispatched only within the remaining budget.
+///
+/// Rate limits are tracked separately for each of the tokens of the authenticated user, and
+/// requests are spread over the tokens in a round-robin manner. The status of the tokens is shared
+/// with the trackers made by `share`, so that a token used by several clients does not spend its
+/// budget twice.
+///
+/// The tracker is meant to be shared by the futures running in a single task, hence the lack of
+/// synchronization.
+pub struct RateLimiter {
+    /// IDs of the tokens (`tokens.id`) to sign requests with.
+    tokens: Vec<i32>,
+    shared: Rc<Shared>,
+    /// Index into `tokens` of the token to try first.
+    next: Cell<usize>,
+    in_flight: Cell<usize>,
+    max_in_flight: usize,
+}
+
+/// The state shared by the trackers made by `RateLimiter::share`.
+#[derive(Default)]
+struct Shared {
+    /// Status of each pair of a token ID and an endpoint.
+    endpoints: RefCell<HashMap<(i32, String), Endpoint>>,
+    /// Wakers of the tasks waiting for a request in flight to complete.
+    waiters: RefCell<Vec<Waker>>,
+}
+
+#[derive(Default)]
+struct Endpoint {
+    /// The latest rate limit status reported by the API.
+    rate_limit: Option<RateLimit>,
+    in_flight: u64,
+    /// Whether `rate_limit` has been updated since the last `take_updates` call.
+    updated: bool,
+}
+
+/// A slot for a request in flight, which is released when dropped.
+pub struct Permit<'a> {
+    limiter: &'a RateLimiter,
+    token: usize,
+    endpoint: &'static str,
+}
+
+/// Outcome of `RateLimiter::try_acquire`.
+pub enum Acquire<'a> {
+    Ready(Permit<'a>),
+    /// The rate limit of the endpoint has been exhausted for every token until the Unix time.
+    Wait(u64),
+    /// Too many requests are in flight. The task will be woken when one of them completes.
+    Busy,
+}
+
+/// Whether a token has budget for a request to an endpoint.
+enum Budget {
+    Available,
+    /// The budget may be left after the requests in flight complete.
+    InFlight,
+    Exhausted {
+        reset: u64,
+    },
+}
+
+impl Endpoint {
+    fn budget(&mut self, now: u64) -> Budget {
+        if let Some(rl) = self.rate_limit {
+            if rl.reset < now {
+                // The rate limit window has been reset.
+                self.rate_limit = None;
+            } else if rl.remaining <= self.in_flight {
+                if self.in_flight > 0 {
+                    return Budget::InFlight;
+                }
+                return Budget::Exhausted { reset: rl.reset };
+            }
+        }
+        Budget::Available
+    }
+}
+
+impl RateLimiter {
+    /// Creates a tracker for the tokens of the given IDs, allowing at most `max_in_flight`
+    /// concurrent requests.
+    pub fn new(tokens: Vec<i32>, max_in_flight: usize) -> Self {
+        RateLimiter::with_shared(tokens, max_in_flight, Rc::default())
+    }
+
+    /// Creates a tracker for the tokens of the given IDs which shares the rate limit status of the
+    /// tokens with this tracker, while limiting the concurrent requests separately.
+    pub fn share(&self, tokens: Vec<i32>, max_in_flight: usize) -> Self {
+        RateLimiter::with_shared(tokens, max_in_flight, self.shared.clone())
+    }
+
+    fn with_shared(tokens: Vec<i32>, max_in_flight: usize, shared: Rc<Shared>) -> Self {
+        RateLimiter {
+            tokens,
+            shared,
+            next: Cell::new(0),
+            in_flight: Cell::new(0),
+            max_in_flight: max_in_flight.max(1),
+        }
+    }
+
+    /// Tries to reserve a slot for a request to `endpoint` with one of the tokens.
+    pub fn try_acquire(&self, endpoint: &'static str, waker: &Waker) -> Acquire<'_> {
+        if self.in_flight.get() >= self.max_in_flight {
+            self.shared.waiters.borrow_mut().push(waker.clone());
+            return Acquire::Busy;
+        }
+
+        let now = now();
+        let n = self.tokens.len();
+        let start = self.next.get();
+        let mut endpoints = self.shared.endpoints.borrow_mut();
+        let mut in_flight = false;
+        let mut reset = u64::MAX;
+        for token in (start..start + n).map(|i| i % n) {
+            let key = (self.tokens[token], endpoint.to_owned());
+            let state = endpoints.entry(key).or_default();
+            match state.budget(now) {
+                Budget::Available => {
+                    state.in_flight += 1;
+                    self.in_flight.set(self.in_flight.get() + 1);
+                    self.next.set((token + 1) % n);
+                    return Acquire::Ready(Permit {
+                        limiter: self,
+                        token,
+                        endpoint,
+                    });
+                }
+                Budget::InFlight => in_flight = true,
+                Budget::Exhausted { reset: r } => reset = reset.min(r),
+            }
+        }
+
+        if in_flight {
+            // The responses in flight will tell whether the budget is left.
+            self.shared.waiters.borrow_mut().push(waker.clone());
+            Acquire::Busy
+        } else {
+            Acquire::Wait(reset)
+        }
+    }
+
+    /// Reserves a slot for a request to `endpoint`, waiting for the rate limit to reset if it
+    /// has been exhausted for every token.
+    pub async fn acquire(&self, endpoint: &'static str) -> Permit<'_> {
+        loop {
+            let acquire =
+                futures::future::poll_fn(|cx| match self.try_acquire(endpoint, cx.waker()) {
+                    Acquire::Busy => Poll::Pending,
+                    acquire => Poll::Ready(acquire),
+                })
+                .await;
+            match acquire {
+                Acquire::Ready(permit) => return permit,
+                Acquire::Wait(reset) => {
+                    log::info!("Rate limit of {} exhausted", endpoint);
+                    wait_until(reset + 1).await;
+                }
+                Acquire::Busy => unreachable!(),
+            }
+        }
+    }
+
+    /// Records the rate limit status of the token of ID `token` reported by a response from
+    /// `endpoint`.
+    pub fn update(&self, token: i32, endpoint: &str, rate_limit: RateLimit) {
+        let mut endpoints = self.shared.endpoints.borrow_mut();
+        let state = endpoints.entry((token, endpoint.to_owned())).or_default();
+        state.updated = true;
+        state.rate_limit = match state.rate_limit {
+            // Responses may arrive out of order, so keep the smallest budget in the same window.
+            Some(rl) if rl.reset == rate_limit.reset => Some(RateLimit {
+                remaining: rl.remaining.min(rate_limit.remaining),
+                reset: rl.reset,
+            }),
+            // A stale response from the previous window.
+            Some(rl) if rl.reset > rate_limit.reset => Some(rl),
+            _ => Some(rate_limit),
+        };
+    }
+
+    /// Returns the IDs of the tracked tokens.
+    pub fn tokens(&self) -> &[i32] {
+        &self.tokens
+    }
+
+    /// Returns the rate limit status of the pairs of a token ID and an endpoint updated since
+    /// the last call (on this tracker or the ones sharing the status), which should be persisted
+    /// (see `query::save_rate_limits`).
+    pub fn take_updates(&self) -> Vec<(i32, String, RateLimit)> {
+        self.shared
+            .endpoints
+            .borrow_mut()
+            .iter_mut()
+            .filter(|(_, state)| state.updated)
+            .filter_map(|(&(token, ref endpoint), state)| {
+                state.updated = false;
+                state.rate_limit.map(|rl| (token, endpoint.clone(), rl))
+            })
+            .collect()
+    }
+}
+
+impl<'a> Permit<'a> {
+    /// Returns the index of the token to sign the request with, in the order of the token IDs
+    /// given to `RateLimiter::new`.
+    pub fn token(&self) -> usize {
+        self.token
+    }
+
+    /// Records the rate limit status reported by the response to the request.
+    pub fn update(&self, rate_limit: Option<RateLimit>) {
+        if let Some(rl) = rate_limit {
+            let token = self.limiter.tokens[self.token];
+            self.limiter.update(token, self.endpoint, rl);
+        }
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        let limiter = self.limiter;
+        let key = (limiter.tokens[self.token], self.endpoint.to_owned());
+        if let Some(state) = limiter.shared.endpoints.borrow_mut().get_mut(&key) {
+            state.in_flight -= 1;
+        }
+        limiter.in_flight.set(limiter.in_flight.get() - 1);
+        // Wake the waiters of the trackers sharing the status as well, since the token may be
+        // theirs too.
+        let waiters = std::mem::take(&mut *limiter.shared.waiters.borrow_mut());
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::task::{waker, ArcWake};
+
+    use super::*;
+
+    const ENDPOINT: &str = "https://api.twitter.com/1.1/blocks/create.json";
+
+    /// Counts the wakes of the waker.
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+
+    impl ArcWake for Wakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Wakes {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn ready(acquire: Acquire<'_>) -> Permit<'_> {
+        match acquire {
+            Acquire::Ready(permit) => permit,
+            Acquire::Wait(reset) => panic!("expected Ready, got Wait({})", reset),
+            Acquire::Busy => panic!("expected Ready, got Busy"),
+        }
+    }
+
+    fn exhausted(reset: u64) -> RateLimit {
+        RateLimit {
+            remaining: 0,
+            reset,
+        }
+    }
+
+    #[test]
+    fn ready_round_robin() {
+        let wakes = Arc::new(Wakes::default());
+        let waker = waker(wakes.clone());
+        let limiter = RateLimiter::new(vec![10, 20], 8);
+
+        let tokens: Vec<_> = (0..3)
+            .map(|_| ready(limiter.try_acquire(ENDPOINT, &waker)).token())
+            .collect();
+        assert_eq!(tokens, [0, 1, 0]);
+        assert_eq!(wakes.count(), 0);
+    }
+
+    #[test]
+    fn wait_for_exhausted_tokens() {
+        let waker = waker(Arc::new(Wakes::default()));
+        let limiter = RateLimiter::new(vec![10, 20], 8);
+        let reset = now() + 60;
+
+        // The other token takes over.
+        limiter.update(10, ENDPOINT, exhausted(reset + 30));
+        let permit = ready(limiter.try_acquire(ENDPOINT, &waker));
+        assert_eq!(permit.token(), 1);
+        drop(permit);
+
+        // Wait for the earliest reset.
+        limiter.update(20, ENDPOINT, exhausted(reset));
+        assert!(matches!(
+            limiter.try_acquire(ENDPOINT, &waker),
+            Acquire::Wait(r) if r == reset
+        ));
+
+        // The rate limits of the other endpoints are separate.
+        let other = "https://api.twitter.com/1.1/blocks/destroy.json";
+        ready(limiter.try_acquire(other, &waker));
+
+        // A window in the past has been reset.
+        let limiter = RateLimiter::new(vec![10], 8);
+        limiter.update(10, ENDPOINT, exhausted(now() - 1));
+        ready(limiter.try_acquire(ENDPOINT, &waker));
+    }
+
+    #[test]
+    fn busy_until_permit_dropped() {
+        let wakes = Arc::new(Wakes::default());
+        let waker = waker(wakes.clone());
+        let limiter = RateLimiter::new(vec![10, 20], 1);
+
+        let permit = ready(limiter.try_acquire(ENDPOINT, &waker));
+        assert!(matches!(
+            limiter.try_acquire(ENDPOINT, &waker),
+            Acquire::Busy
+        ));
+        assert_eq!(wakes.count(), 0);
+
+        drop(permit);
+        assert_eq!(wakes.count(), 1);
+        ready(limiter.try_acquire(ENDPOINT, &waker));
+    }
+
+    #[test]
+    fn busy_while_budget_in_flight() {
+        let wakes = Arc::new(Wakes::default());
+        let waker = waker(wakes.clone());
+        let limiter = RateLimiter::new(vec![10], 8);
+        let reset = now() + 60;
+        limiter.update(
+            10,
+            ENDPOINT,
+            RateLimit {
+                remaining: 1,
+                reset,
+            },
+        );
+
+        // The last request of the window is in flight, whose response tells whether the budget
+        // is left.
+        let permit = ready(limiter.try_acquire(ENDPOINT, &waker));
+        assert!(matches!(
+            limiter.try_acquire(ENDPOINT, &waker),
+            Acquire::Busy
+        ));
+
+        permit.update(Some(exhausted(reset)));
+        drop(permit);
+        assert_eq!(wakes.count(), 1);
+        assert!(matches!(
+            limiter.try_acquire(ENDPOINT, &waker),
+            Acquire::Wait(r) if r == reset
+        ));
+
+        // The exhausted rate limit is reported to be persisted.
+        let updates = limiter.take_updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!((updates[0].0, updates[0].2.remaining), (10, 0));
+        assert!(limiter.take_updates().is_empty());
+    }
+
+    #[test]
+    fn shared_status() {
+        let wakes = Arc::new(Wakes::default());
+        let waker = waker(wakes.clone());
+        let limiter = RateLimiter::new(vec![10, 20], 1);
+        let shared = limiter.share(vec![20], 1);
+        let reset = now() + 60;
+
+        // The window spent through one tracker is spent for the other one as well.
+        limiter.update(20, ENDPOINT, exhausted(reset));
+        assert!(matches!(
+            shared.try_acquire(ENDPOINT, &waker),
+            Acquire::Wait(r) if r == reset
+        ));
+        assert_eq!(shared.take_updates().len(), 1);
+        assert!(limiter.take_updates().is_empty());
+
+        // The concurrent requests are limited separately, and a waiter of one tracker is woken
+        // by a permit dropped by the other.
+        let permit = ready(limiter.try_acquire(ENDPOINT, &waker));
+        assert!(matches!(
+            limiter.try_acquire(ENDPOINT, &waker),
+            Acquire::Busy
+        ));
+        let other = "https://api.twitter.com/1.1/blocks/destroy.json";
+        let shared_permit = ready(shared.try_acquire(other, &waker));
+        drop(shared_permit);
+        assert_eq!(wakes.count(), 1);
+        drop(permit);
+    }
+}
@@ -0,0 +1,310 @@
+use std::rc::Rc;
+
+use futures::future::{FutureExt, LocalBoxFuture};
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use structopt::StructOpt;
+
+use crate::error::Error;
+
+use super::API_BASE;
+
+/// Options to specify the API to send the requests to.
+#[derive(StructOpt)]
+pub struct ApiOpts {
+    /// Base URL of the API, which replaces `https://api.twitter.com` in the request URLs (e.g. to
+    /// send the requests through a proxy or to a mock server)
+    #[structopt(long, default_value = API_BASE, env = "ABYSS_BLOCKER_API_BASE")]
+    api_base: String,
+}
+
+impl ApiOpts {
+    /// Returns a client sending the requests to the API with `reqwest`.
+    pub fn http(&self) -> Http {
+        Http::new(reqwest::Client::new(), &self.api_base)
+    }
+}
+
+/// A request to the API, signed with OAuth.
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    /// The URI including the query string.
+    pub uri: String,
+    /// The value of the `Authorization` header.
+    pub authorization: String,
+    /// The form-encoded body of a `POST` request.
+    pub body: Option<String>,
+}
+
+/// A response from the API, whose body has been read to the end.
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Deserializes the JSON body.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// A way to send the requests to the API.
+pub trait Transport {
+    fn send(&self, request: Request) -> LocalBoxFuture<'_, Result<Response, Error>>;
+}
+
+impl Transport for reqwest::Client {
+    fn send(&self, request: Request) -> LocalBoxFuture<'_, Result<Response, Error>> {
+        let mut builder = self
+            .request(request.method, &request.uri)
+            .header(AUTHORIZATION, request.authorization);
+        if let Some(body) = request.body {
+            builder = builder
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(body);
+        }
+        async move {
+            let response = builder.send().await?;
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = response.bytes().await?.to_vec();
+            Ok(Response {
+                status,
+                headers,
+                body,
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// A client of the API, which sends the requests with a `Transport` to the API at a configurable
+/// base URL.
+///
+/// The endpoints are identified by their URIs in `twitter::api` (at `https://api.twitter.com`)
+/// throughout the crate, e.g. in the rate limits and the database, and are translated with `uri`
+/// only when a request is signed.
+///
+/// Cloning an `Http` shares the `Transport`.
+#[derive(Clone)]
+pub struct Http {
+    transport: Rc<dyn Transport>,
+    base: String,
+}
+
+impl Http {
+    pub fn new(transport: impl Transport + 'static, base: &str) -> Self {
+        Http {
+            transport: Rc::new(transport),
+            base: base.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// Returns the URI to send the requests to `endpoint` to, which is signed with OAuth.
+    pub fn uri(&self, endpoint: &str) -> String {
+        match endpoint.strip_prefix(API_BASE) {
+            Some(path) => format!("{}{}", self.base, path),
+            None => endpoint.to_owned(),
+        }
+    }
+
+    /// Sends a `GET` request, where `request.data` is the URI signed with `uri`.
+    pub fn get(&self, request: oauth::Request) -> LocalBoxFuture<'_, Result<Response, Error>> {
+        self.transport.send(Request {
+            method: Method::GET,
+            uri: request.data,
+            authorization: request.authorization,
+            body: None,
+        })
+    }
+
+    /// Sends a `POST` request to `endpoint`, where `request.data` is the form-encoded body signed
+    /// for `uri(endpoint)`.
+    pub fn post_form(
+        &self,
+        endpoint: &str,
+        request: oauth::Request,
+    ) -> LocalBoxFuture<'_, Result<Response, Error>> {
+        self.transport.send(Request {
+            method: Method::POST,
+            uri: self.uri(endpoint),
+            authorization: request.authorization,
+            body: Some(request.data),
+        })
+    }
+}
+
+#[cfg(test)]
+pub use self::fake::Fake;
+
+#[cfg(test)]
+mod fake {
+    use std::cell::RefCell;
+    use std::collections::{HashMap, VecDeque};
+    use std::rc::Rc;
+
+    use futures::future::{self, FutureExt, LocalBoxFuture};
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    use super::{Request, Response, Transport};
+    use crate::error::Error;
+
+    /// An in-memory `Transport` that returns canned responses and records the requests.
+    ///
+    /// The responses are looked up by the URI without the query string. The responses queued by
+    /// `respond_once` are returned first, and then the one set by `respond` for every request.
+    #[derive(Clone, Default)]
+    pub struct Fake {
+        state: Rc<RefCell<State>>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        once: HashMap<String, VecDeque<Canned>>,
+        always: HashMap<String, Canned>,
+        requests: Vec<Request>,
+    }
+
+    #[derive(Clone)]
+    struct Canned {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: String,
+    }
+
+    impl Fake {
+        /// Responds to every request to `uri` with `status` and `body`.
+        pub fn respond(&self, uri: &str, status: u16, body: &str) {
+            let canned = Canned::new(status, body, None);
+            self.state
+                .borrow_mut()
+                .always
+                .insert(uri.to_owned(), canned);
+        }
+
+        /// Responds to the next request to `uri` with `status` and `body`, with the rate limit
+        /// headers if `rate_limit` is `Some((remaining, reset))`.
+        pub fn respond_once(
+            &self,
+            uri: &str,
+            status: u16,
+            body: &str,
+            rate_limit: Option<(u64, u64)>,
+        ) {
+            let canned = Canned::new(status, body, rate_limit);
+            let mut state = self.state.borrow_mut();
+            state
+                .once
+                .entry(uri.to_owned())
+                .or_default()
+                .push_back(canned);
+        }
+
+        /// Returns the URIs (including the query strings) of the requests sent so far.
+        pub fn requests(&self) -> Vec<String> {
+            let state = self.state.borrow();
+            state.requests.iter().map(|r| r.uri.clone()).collect()
+        }
+
+        /// Returns the number of the requests sent to `uri` so far.
+        pub fn count(&self, uri: &str) -> usize {
+            let state = self.state.borrow();
+            state
+                .requests
+                .iter()
+                .filter(|r| r.uri.split('?').next() == Some(uri))
+                .count()
+        }
+    }
+
+    impl Canned {
+        fn new(status: u16, body: &str, rate_limit: Option<(u64, u64)>) -> Self {
+            let mut headers = HeaderMap::new();
+            if let Some((remaining, reset)) = rate_limit {
+                headers.insert("x-rate-limit-remaining", HeaderValue::from(remaining));
+                headers.insert("x-rate-limit-reset", HeaderValue::from(reset));
+            }
+            Canned {
+                status: StatusCode::from_u16(status).unwrap(),
+                headers,
+                body: body.to_owned(),
+            }
+        }
+    }
+
+    impl Transport for Fake {
+        fn send(&self, request: Request) -> LocalBoxFuture<'_, Result<Response, Error>> {
+            let mut state = self.state.borrow_mut();
+            let uri = request.uri.split('?').next().unwrap().to_owned();
+            let canned = state
+                .once
+                .get_mut(&uri)
+                .and_then(VecDeque::pop_front)
+                .or_else(|| state.always.get(&uri).cloned())
+                .unwrap_or_else(|| panic!("unexpected request: {}", request.uri));
+            state.requests.push(request);
+            future::ready(Ok(Response {
+                status: canned.status,
+                headers: canned.headers,
+                body: canned.body.into_bytes(),
+            }))
+            .boxed_local()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::twitter;
+
+    #[test]
+    fn uri() {
+        let http = Http::new(Fake::default(), "http://127.0.0.1:8080/");
+        assert_eq!(
+            http.uri(twitter::USERS_LOOKUP),
+            "http://127.0.0.1:8080/1.1/users/lookup.json",
+        );
+        assert_eq!(
+            http.uri(twitter::OAUTH_REQUEST_TOKEN),
+            "http://127.0.0.1:8080/oauth/request_token",
+        );
+
+        let http = Http::new(Fake::default(), API_BASE);
+        assert_eq!(http.uri(twitter::USERS_LOOKUP), twitter::USERS_LOOKUP);
+    }
+
+    #[tokio::test]
+    async fn fake() {
+        let fake = Fake::default();
+        let http = Http::new(fake.clone(), API_BASE);
+        fake.respond(twitter::USERS_LOOKUP, 200, "[]");
+        fake.respond_once(twitter::USERS_LOOKUP, 429, "{}", Some((0, 1234)));
+
+        let get = || {
+            http.get(oauth::Request {
+                authorization: "OAuth".to_owned(),
+                data: format!("{}?user_id=1", twitter::USERS_LOOKUP),
+            })
+        };
+        let response = get().await.unwrap();
+        assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+        let rl = twitter::rate_limit(&response.headers).unwrap();
+        assert_eq!((rl.remaining, rl.reset), (0, 1234));
+        let response = get().await.unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json::<Vec<i64>>().unwrap(), Vec::<i64>::new());
+
+        assert_eq!(fake.count(twitter::USERS_LOOKUP), 2);
+        assert_eq!(
+            fake.requests()[0],
+            format!("{}?user_id=1", twitter::USERS_LOOKUP),
+        );
+    }
+}
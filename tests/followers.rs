@@ -0,0 +1,379 @@
+//! Runs `followers` against the mock API in `mock`.
+
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+mod mock;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+
+use mock::{Fixture, Server};
+
+const BIN: &str = env!("CARGO_BIN_EXE_abyss-blocker");
+
+const EX_UNAVAILABLE: i32 = 69;
+const EX_NOPERM: i32 = 77;
+
+#[derive(QueryableByName)]
+struct Block {
+    #[sql_type = "BigInt"]
+    source: i64,
+    #[sql_type = "BigInt"]
+    target: i64,
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[sql_type = "BigInt"]
+    n: i64,
+}
+
+#[derive(QueryableByName)]
+struct User {
+    #[sql_type = "Nullable<Text>"]
+    screen_name: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct RateLimit {
+    #[sql_type = "Integer"]
+    token: i32,
+    #[sql_type = "BigInt"]
+    remaining: i64,
+}
+
+/// A database and a mock API to run the commands against.
+struct Env {
+    db: PathBuf,
+    server: Server,
+}
+
+impl Env {
+    fn new(name: &str, fixture: Fixture) -> Self {
+        let db = std::env::temp_dir().join(format!(
+            "abyss-blocker-it-{}-{}.sqlite3",
+            name,
+            std::process::id(),
+        ));
+        let _ = std::fs::remove_file(&db);
+
+        let conn = SqliteConnection::establish(db.to_str().unwrap()).unwrap();
+        let mut migrations: Vec<_> =
+            std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.is_dir())
+                .collect();
+        migrations.sort();
+        for dir in migrations {
+            let sql = std::fs::read_to_string(dir.join("up.sql")).unwrap();
+            conn.batch_execute(&sql).unwrap();
+        }
+
+        Env {
+            db,
+            server: Server::start(fixture),
+        }
+    }
+
+    fn db(&self) -> &str {
+        self.db.to_str().unwrap()
+    }
+
+    fn conn(&self) -> SqliteConnection {
+        SqliteConnection::establish(self.db()).unwrap()
+    }
+
+    /// Runs the command with `--database` and `--api-base`, feeding `stdin` to it.
+    fn run(&self, args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(BIN)
+            .args(args)
+            .args(["--database", self.db(), "--api-base", &self.server.base()])
+            .env_remove("ABYSS_BLOCKER_PASSPHRASE")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Stores the token, verifying it with the mock API.
+    fn authorize(&self, (token, secret): &(String, String)) {
+        let stdin = format!(
+            "{}\n{}\n{}\n{}\n",
+            mock::CONSUMER_KEY,
+            mock::CONSUMER_SECRET,
+            token,
+            secret,
+        );
+        let output = self.run(&["authorize"], &stdin);
+        assert_success(&output);
+    }
+
+    /// Runs `followers` as user 1.
+    fn followers(&self, args: &[&str]) -> Output {
+        let mut command = vec!["followers", "--login", "1"];
+        command.extend(args);
+        self.run(&command, "")
+    }
+
+    /// Returns the `(source, target)` pairs in the `blocks` table.
+    fn blocks(&self) -> Vec<(i64, i64)> {
+        sql_query("SELECT source, target FROM blocks ORDER BY source, target")
+            .load::<Block>(&self.conn())
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.source, b.target))
+            .collect()
+    }
+
+    fn count(&self, table: &str) -> i64 {
+        sql_query(format!("SELECT COUNT(*) AS n FROM {}", table))
+            .get_result::<Count>(&self.conn())
+            .unwrap()
+            .n
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.db);
+    }
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "the command failed with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr),
+    );
+}
+
+#[test]
+fn blocks_followers_who_block_you() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, vec![2, 3, 4, 5]);
+    fixture.blocks.insert((2, 1));
+    fixture.blocks.insert((4, 1));
+    fixture.blocks.insert((1, 4));
+    let env = Env::new("blocks_followers_who_block_you", fixture);
+    env.authorize(&token);
+
+    let output = env.followers(&["10"]);
+    assert_success(&output);
+
+    // User 4 has already been blocked.
+    assert!(env.server.blocks(1, 2));
+    assert_eq!(env.server.requests_to(mock::BLOCKS_CREATE).len(), 1);
+    assert_eq!(env.blocks(), [(1, 2), (2, 1), (4, 1)]);
+    assert_eq!(env.count("pending_actions"), 0);
+
+    // The profile has been recorded on `authorize`.
+    let user = sql_query("SELECT screen_name FROM user_profiles WHERE user = 1")
+        .get_result::<User>(&env.conn())
+        .unwrap();
+    assert_eq!(user.screen_name.as_deref(), Some("user1"));
+}
+
+#[test]
+fn ids_strategy() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, vec![2, 3, 4]);
+    fixture.blocks.insert((3, 1));
+    let env = Env::new("ids_strategy", fixture);
+    env.authorize(&token);
+
+    let output = env.followers(&["10", "--strategy", "ids"]);
+    assert_success(&output);
+
+    assert_eq!(env.server.requests_to(mock::FOLLOWERS_IDS).len(), 1);
+    let lookups = env.server.requests_to(mock::USERS_LOOKUP);
+    assert_eq!(lookups.len(), 1);
+    assert_eq!(
+        lookups[0]
+            .param("user_id")
+            .map(|ids| ids.split(',').count()),
+        Some(3)
+    );
+    assert!(env.server.blocks(1, 3));
+    assert_eq!(env.count("pending_lookups"), 0);
+}
+
+#[test]
+fn resumes_after_server_error() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, (2..=7).collect());
+    fixture.blocks.insert((3, 1));
+    fixture.blocks.insert((6, 1));
+    fixture.page_size = Some(2);
+    fixture.pass(mock::FOLLOWERS_LIST);
+    fixture.fail(mock::FOLLOWERS_LIST, 503);
+    let env = Env::new("resumes_after_server_error", fixture);
+    env.authorize(&token);
+
+    let output = env.followers(&["10"]);
+    assert_eq!(output.status.code(), Some(EX_UNAVAILABLE));
+    assert!(!env.server.blocks(1, 6));
+
+    let output = env.followers(&["10"]);
+    assert_success(&output);
+
+    // The second run resumes from the page that failed.
+    let pages: Vec<_> = env
+        .server
+        .requests_to(mock::FOLLOWERS_LIST)
+        .iter()
+        .map(|r| r.param("cursor").unwrap().to_owned())
+        .collect();
+    assert_eq!(pages, ["-1", "2", "2", "4"]);
+    assert!(env.server.blocks(1, 3));
+    assert!(env.server.blocks(1, 6));
+    assert_eq!(env.count("pending_actions"), 0);
+}
+
+#[test]
+fn switches_tokens_on_rate_limit() {
+    let mut fixture = Fixture::new();
+    let first = fixture.token(1);
+    let second = fixture.token(1);
+    fixture.followers.insert(10, vec![2]);
+    fixture.blocks.insert((2, 1));
+    fixture.rate_limit = Some(15);
+    fixture.fail(mock::FOLLOWERS_LIST, 429);
+    let env = Env::new("switches_tokens_on_rate_limit", fixture);
+    env.authorize(&first);
+    env.authorize(&second);
+
+    let output = env.followers(&["10"]);
+    assert_success(&output);
+
+    let requests = env.server.requests_to(mock::FOLLOWERS_LIST);
+    assert_eq!(requests.len(), 2);
+    assert_ne!(requests[0].token, requests[1].token);
+    assert!(env.server.blocks(1, 2));
+
+    // The exhausted window of the first token is persisted for the next run.
+    let rate_limits = sql_query(
+        "SELECT token, remaining FROM rate_limits \
+         JOIN endpoints ON rate_limits.endpoint = endpoints.id \
+         WHERE endpoints.uri LIKE '%/followers/list.json' \
+         ORDER BY token",
+    )
+    .load::<RateLimit>(&env.conn())
+    .unwrap();
+    let remaining: Vec<_> = rate_limits
+        .iter()
+        .map(|rl| (rl.token, rl.remaining))
+        .collect();
+    assert_eq!(remaining, [(1, 0), (2, 14)]);
+}
+
+#[test]
+fn retries_failed_blocks() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, vec![2, 3]);
+    fixture.blocks.insert((2, 1));
+    fixture.fail(mock::BLOCKS_CREATE, 500);
+    let env = Env::new("retries_failed_blocks", fixture);
+    env.authorize(&token);
+
+    let output = env.followers(&["10", "--max-attempts", "1"]);
+    assert_success(&output);
+    assert!(!env.server.blocks(1, 2));
+    assert_eq!(env.count("failed_actions"), 1);
+
+    let output = env.followers(&["10", "--retry-failed"]);
+    assert_success(&output);
+    assert!(env.server.blocks(1, 2));
+    assert_eq!(env.count("failed_actions"), 0);
+    assert_eq!(env.count("pending_actions"), 0);
+}
+
+#[test]
+fn unblocks_users() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, vec![2, 3]);
+    fixture.blocks.insert((2, 1));
+    fixture.blocks.insert((3, 1));
+    let env = Env::new("unblocks_users", fixture);
+    env.authorize(&token);
+
+    assert_success(&env.followers(&["10"]));
+    assert!(env.server.blocks(1, 2));
+    assert!(env.server.blocks(1, 3));
+
+    let output = env.run(&["unblock", "2", "--login", "1"], "");
+    assert_success(&output);
+
+    let requests = env.server.requests_to(mock::BLOCKS_DESTROY);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].param("user_id"), Some("2"));
+    assert!(!env.server.blocks(1, 2));
+    assert_eq!(env.blocks(), [(1, 3), (2, 1), (3, 1)]);
+    assert_eq!(env.count("pending_actions"), 0);
+}
+
+#[test]
+fn mutes_users() {
+    let mut fixture = Fixture::new();
+    let token = fixture.token(1);
+    fixture.followers.insert(10, vec![2, 3]);
+    fixture.blocks.insert((2, 1));
+    fixture.blocks.insert((3, 1));
+    fixture.mutes.insert((1, 3));
+    let env = Env::new("mutes_users", fixture);
+    env.authorize(&token);
+
+    let output = env.followers(&["10", "--action", "mute"]);
+    assert_success(&output);
+
+    // User 3 has already been muted.
+    let requests = env.server.requests_to(mock::MUTES_USERS_CREATE);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].param("user_id"), Some("2"));
+    assert!(env.server.mutes(1, 2));
+    assert!(env.server.requests_to(mock::BLOCKS_CREATE).is_empty());
+    assert_eq!(env.count("mutes"), 1);
+    assert_eq!(env.count("pending_actions"), 0);
+}
+
+#[test]
+fn rejects_invalid_token() {
+    let mut fixture = Fixture::new();
+    fixture.token(1);
+    fixture.followers.insert(10, vec![2]);
+    let env = Env::new("rejects_invalid_token", fixture);
+    // A token unknown to the API, stored without verification
+    let stdin = format!(
+        "{}\n{}\n1-revoked\nsecret\n",
+        mock::CONSUMER_KEY,
+        mock::CONSUMER_SECRET,
+    );
+    assert_success(&env.run(&["authorize", "--no-verify"], &stdin));
+
+    let output = env.followers(&["10"]);
+    assert_eq!(output.status.code(), Some(EX_NOPERM));
+    assert!(env.server.requests().is_empty());
+}
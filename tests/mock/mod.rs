@@ -0,0 +1,542 @@
+//! A mock of the Twitter API serving a scripted `Fixture` over HTTP, for the integration tests.
+//!
+//! The server validates the OAuth signatures made by `oauth1-request` against the credentials in
+//! the fixture, tracks rate limit windows for each token and endpoint, and injects the error
+//! responses queued in the fixture.
+
+#![allow(dead_code)]
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+pub const FOLLOWERS_LIST: &str = "/1.1/followers/list.json";
+pub const FOLLOWERS_IDS: &str = "/1.1/followers/ids.json";
+pub const USERS_LOOKUP: &str = "/1.1/users/lookup.json";
+pub const BLOCKS_CREATE: &str = "/1.1/blocks/create.json";
+pub const BLOCKS_DESTROY: &str = "/1.1/blocks/destroy.json";
+pub const MUTES_USERS_CREATE: &str = "/1.1/mutes/users/create.json";
+pub const ACCOUNT_VERIFY_CREDENTIALS: &str = "/1.1/account/verify_credentials.json";
+
+pub const CONSUMER_KEY: &str = "consumer-key";
+pub const CONSUMER_SECRET: &str = "consumer-secret";
+
+/// The scripted state of the mock API.
+#[derive(Default)]
+pub struct Fixture {
+    /// Access tokens and their secrets, by the user who authorized them. All the tokens are
+    /// issued for the consumer `CONSUMER_KEY`.
+    pub tokens: HashMap<String, (String, i64)>,
+    /// Screen names of the users.
+    pub screen_names: HashMap<i64, String>,
+    /// Followers of each user, in the order they are listed.
+    pub followers: HashMap<i64, Vec<i64>>,
+    /// Pairs of `(source, target)` where `source` blocks `target`.
+    pub blocks: HashSet<(i64, i64)>,
+    /// Pairs of `(source, target)` where `source` mutes `target`.
+    pub mutes: HashSet<(i64, i64)>,
+    /// Overrides the `count` parameter of the paginated endpoints to force pagination.
+    pub page_size: Option<usize>,
+    /// Number of requests allowed per rate limit window for each token and endpoint.
+    pub rate_limit: Option<u64>,
+    /// Length of the rate limit windows in seconds.
+    pub window: u64,
+    /// Statuses of the error responses to inject into the next requests to each endpoint path,
+    /// where `None` lets the request through.
+    pub failures: HashMap<&'static str, VecDeque<Option<u16>>>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Fixture {
+            window: 15 * 60,
+            ..Fixture::default()
+        }
+    }
+
+    /// Issues an access token to `user` and returns its `(token, secret)`.
+    pub fn token(&mut self, user: i64) -> (String, String) {
+        let n = self.tokens.len();
+        let token = format!("{}-token{}", user, n);
+        let secret = format!("secret{}", n);
+        self.tokens.insert(token.clone(), (secret.clone(), user));
+        (token, secret)
+    }
+
+    /// Injects an error response with `status` into the next request to `path` (after the ones
+    /// already scripted).
+    pub fn fail(&mut self, path: &'static str, status: u16) {
+        self.failures
+            .entry(path)
+            .or_default()
+            .push_back(Some(status));
+    }
+
+    /// Lets the next request to `path` (after the ones already scripted) through.
+    pub fn pass(&mut self, path: &'static str) {
+        self.failures.entry(path).or_default().push_back(None);
+    }
+}
+
+/// A request received by the server.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+    /// The user who authorized the token the request was signed with.
+    pub user: i64,
+    pub token: String,
+}
+
+impl Request {
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| &**v)
+    }
+}
+
+struct State {
+    fixture: Fixture,
+    /// `(remaining, reset)` of the rate limit window of each token and endpoint path.
+    windows: HashMap<(String, String), (u64, u64)>,
+    requests: Vec<Request>,
+}
+
+pub struct Server {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl Server {
+    /// Starts serving `fixture` on a local port.
+    pub fn start(fixture: Fixture) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            fixture,
+            windows: HashMap::new(),
+            requests: Vec::new(),
+        }));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    let _ = handle_connection(stream, addr, &state);
+                });
+            }
+        });
+
+        Server { addr, state }
+    }
+
+    /// Returns the base URL to pass to `--api-base`.
+    pub fn base(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns the requests authenticated so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state().requests.clone()
+    }
+
+    /// Returns the requests to `path` authenticated so far.
+    pub fn requests_to(&self, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.path == path)
+            .collect()
+    }
+
+    /// Calls `f` with the fixture, e.g. to inject errors between runs.
+    pub fn with_fixture<T>(&self, f: impl FnOnce(&mut Fixture) -> T) -> T {
+        f(&mut self.state().fixture)
+    }
+
+    /// Returns whether `source` blocks `target` in the mock.
+    pub fn blocks(&self, source: i64, target: i64) -> bool {
+        self.state().fixture.blocks.contains(&(source, target))
+    }
+
+    /// Returns whether `source` mutes `target` in the mock.
+    pub fn mutes(&self, source: i64, target: i64) -> bool {
+        self.state().fixture.mutes.contains(&(source, target))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(value: Value) -> Self {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: value.to_string(),
+        }
+    }
+
+    fn error(status: u16, code: u32, message: &str) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: json!({ "errors": [{ "code": code, "message": message }] }).to_string(),
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    state: &Mutex<State>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut split = request_line.split_whitespace();
+    let method = split.next().unwrap_or("").to_owned();
+    let target = split.next().unwrap_or("").to_owned();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(i) = line.find(':') {
+            let (name, value) = line.split_at(i);
+            headers.insert(name.to_ascii_lowercase(), value[1..].trim().to_owned());
+        }
+    }
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body).into_owned();
+
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap().to_owned();
+    let mut params = parse_form(target.next().unwrap_or(""));
+    if method == "POST" {
+        params.extend(parse_form(&body));
+    }
+
+    let authorization = headers.get("authorization").map(|v| &**v).unwrap_or("");
+    let uri = format!("http://{}{}", addr, path);
+    let response = {
+        let mut state = state.lock().unwrap();
+        handle(&mut state, &method, &uri, path, params, authorization)
+    };
+
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} Mock\r\n", response.status)?;
+    write!(stream, "Content-Type: application/json\r\n")?;
+    write!(stream, "Content-Length: {}\r\n", response.body.len())?;
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(stream, "Connection: close\r\n\r\n{}", response.body)?;
+    stream.flush()
+}
+
+fn handle(
+    state: &mut State,
+    method: &str,
+    uri: &str,
+    path: String,
+    params: Vec<(String, String)>,
+    authorization: &str,
+) -> Response {
+    let (user, token) = match authenticate(&state.fixture, method, uri, &params, authorization) {
+        Ok(auth) => auth,
+        Err(response) => return response,
+    };
+
+    let request = Request {
+        method: method.to_owned(),
+        path,
+        params,
+        user,
+        token,
+    };
+    state.requests.push(request.clone());
+
+    // Rate limit window of the token and endpoint
+    let now = now();
+    let window = state.fixture.window;
+    let limit = state.fixture.rate_limit;
+    let injected = state
+        .fixture
+        .failures
+        .get_mut(&*request.path)
+        .and_then(VecDeque::pop_front)
+        .flatten();
+    let rate_limit = limit.map(|limit| {
+        let key = (request.token.clone(), request.path.clone());
+        let entry = state.windows.entry(key).or_insert((limit, now + window));
+        if entry.1 <= now {
+            *entry = (limit, now + window);
+        }
+        entry
+    });
+    let exhausted = rate_limit.as_ref().is_some_and(|window| window.0 == 0);
+
+    let mut response = match injected {
+        _ if exhausted => Response::error(429, 88, "Rate limit exceeded"),
+        Some(429) => {
+            // Exhaust the window, as if the requests were made by another client.
+            if let Some(window) = rate_limit {
+                window.0 = 0;
+            }
+            Response::error(429, 88, "Rate limit exceeded")
+        }
+        Some(status) => Response::error(status, 131, "Internal error"),
+        None => {
+            if let Some(window) = rate_limit {
+                window.0 -= 1;
+            }
+            route(&mut state.fixture, &request)
+        }
+    };
+
+    let key = (request.token.clone(), request.path.clone());
+    if let Some(&(remaining, reset)) = state.windows.get(&key) {
+        let limit = limit.unwrap();
+        response
+            .headers
+            .push(("x-rate-limit-limit", limit.to_string()));
+        response
+            .headers
+            .push(("x-rate-limit-remaining", remaining.to_string()));
+        response
+            .headers
+            .push(("x-rate-limit-reset", reset.to_string()));
+    }
+
+    response
+}
+
+/// Validates the OAuth signature of the request, and returns the user who authorized the token
+/// and the token.
+fn authenticate(
+    fixture: &Fixture,
+    method: &str,
+    uri: &str,
+    params: &[(String, String)],
+    authorization: &str,
+) -> Result<(i64, String), Response> {
+    let unauthorized = || Response::error(401, 32, "Could not authenticate you.");
+
+    let oauth = match authorization.strip_prefix("OAuth ") {
+        Some(oauth) => oauth,
+        None => return Err(unauthorized()),
+    };
+    let oauth: HashMap<String, String> = oauth
+        .split(',')
+        .filter_map(|pair| {
+            let mut split = pair.trim().splitn(2, '=');
+            let k = split.next()?;
+            let v = split.next()?.trim_matches('"');
+            Some((k.to_owned(), percent_decode(v)))
+        })
+        .collect();
+    let get = |k: &str| oauth.get(k).map(|v| &**v);
+
+    if get("oauth_consumer_key") != Some(CONSUMER_KEY)
+        || get("oauth_signature_method") != Some("HMAC-SHA1")
+    {
+        return Err(unauthorized());
+    }
+    let token = get("oauth_token").ok_or_else(unauthorized)?;
+    let (secret, user) = match fixture.tokens.get(token) {
+        Some(&(ref secret, user)) => (secret, user),
+        None => return Err(Response::error(401, 89, "Invalid or expired token.")),
+    };
+    let nonce = get("oauth_nonce").ok_or_else(unauthorized)?;
+    let timestamp = get("oauth_timestamp")
+        .and_then(|t| t.parse::<u64>().ok())
+        .ok_or_else(unauthorized)?;
+
+    let client = oauth::Credentials::new(CONSUMER_KEY, CONSUMER_SECRET);
+    let mut builder = oauth::Builder::new(client, oauth::HmacSha1);
+    builder
+        .token(oauth::Credentials::new(token, &**secret))
+        .nonce(nonce)
+        .timestamp(timestamp)
+        .version(get("oauth_version").is_some());
+    let params: BTreeSet<(&str, &str)> = params.iter().map(|(k, v)| (&**k, &**v)).collect();
+    let expected = match method {
+        "POST" => builder.post_form(uri, &params),
+        _ => builder.build(method, uri, &params),
+    };
+
+    let signature = expected
+        .authorization
+        .rsplit("oauth_signature=\"")
+        .next()
+        .and_then(|s| s.split('"').next())
+        .map(percent_decode);
+    if signature.as_deref() != get("oauth_signature") {
+        return Err(unauthorized());
+    }
+
+    Ok((user, token.to_owned()))
+}
+
+fn route(fixture: &mut Fixture, request: &Request) -> Response {
+    let auth = request.user;
+    let param = |key: &str| request.param(key).and_then(|v| v.parse::<i64>().ok());
+
+    match (&*request.method, &*request.path) {
+        ("GET", FOLLOWERS_LIST) | ("GET", FOLLOWERS_IDS) => {
+            let target = param("user_id").unwrap();
+            let followers = fixture.followers.get(&target).cloned().unwrap_or_default();
+            let count = fixture
+                .page_size
+                .unwrap_or_else(|| param("count").unwrap_or(20) as usize);
+            let start = match param("cursor").unwrap_or(-1) {
+                -1 => 0,
+                cursor => cursor as usize,
+            };
+            let end = (start + count).min(followers.len());
+            let page = &followers[start.min(end)..end];
+            let next_cursor = if end < followers.len() { end as i64 } else { 0 };
+            let previous_cursor = if start > 0 { -(start as i64) } else { 0 };
+
+            if request.path == FOLLOWERS_IDS {
+                Response::json(json!({
+                    "ids": page,
+                    "next_cursor": next_cursor,
+                    "previous_cursor": previous_cursor,
+                }))
+            } else {
+                let users: Vec<_> = page.iter().map(|&id| user(fixture, auth, id)).collect();
+                Response::json(json!({
+                    "users": users,
+                    "next_cursor": next_cursor,
+                    "previous_cursor": previous_cursor,
+                }))
+            }
+        }
+        ("GET", USERS_LOOKUP) => {
+            let users: Vec<_> = request
+                .param("user_id")
+                .unwrap_or("")
+                .split(',')
+                .filter_map(|id| id.parse().ok())
+                .map(|id| user(fixture, auth, id))
+                .collect();
+            if users.is_empty() {
+                Response::error(404, 17, "No user matches for specified terms.")
+            } else {
+                Response::json(Value::Array(users))
+            }
+        }
+        ("POST", BLOCKS_CREATE) => {
+            let target = param("user_id").unwrap();
+            fixture.blocks.insert((auth, target));
+            Response::json(user(fixture, auth, target))
+        }
+        ("POST", BLOCKS_DESTROY) => {
+            let target = param("user_id").unwrap();
+            fixture.blocks.remove(&(auth, target));
+            Response::json(user(fixture, auth, target))
+        }
+        ("POST", MUTES_USERS_CREATE) => {
+            let target = param("user_id").unwrap();
+            fixture.mutes.insert((auth, target));
+            Response::json(user(fixture, auth, target))
+        }
+        ("GET", ACCOUNT_VERIFY_CREDENTIALS) => Response::json(user(fixture, auth, auth)),
+        _ => Response::error(404, 34, "Sorry, that page does not exist."),
+    }
+}
+
+/// Returns the user object of `id` as seen by `auth`.
+fn user(fixture: &Fixture, auth: i64, id: i64) -> Value {
+    let screen_name = fixture
+        .screen_names
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(|| format!("user{}", id));
+    json!({
+        "id": id,
+        "screen_name": screen_name,
+        "name": format!("User {}", id),
+        "description": "",
+        "location": "",
+        "url": null,
+        "created_at": "Wed Oct 10 20:19:24 +0000 2018",
+        "followers_count": fixture.followers.get(&id).map_or(0, Vec::len),
+        "friends_count": 0,
+        "statuses_count": 0,
+        "protected": false,
+        "verified": false,
+        "default_profile_image": true,
+        "blocking": fixture.blocks.contains(&(auth, id)),
+        "blocked_by": fixture.blocks.contains(&(id, auth)),
+        "muting": fixture.mutes.contains(&(auth, id)),
+    })
+}
+
+fn parse_form(s: &str) -> Vec<(String, String)> {
+    s.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut split = pair.splitn(2, '=');
+            let k = percent_decode(split.next().unwrap());
+            let v = percent_decode(split.next().unwrap_or(""));
+            (k, v)
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(b) = u8::from_str_radix(&s[i + 1..i + 3], 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}